@@ -0,0 +1,7 @@
+ALTER TYPE recurrence_type ADD VALUE IF NOT EXISTS 'rule';
+
+ALTER TABLE recurrences
+    ADD COLUMN rrule TEXT,
+    ADD COLUMN dtstart TIMESTAMP,
+    ADD COLUMN duration INTEGER,
+    ADD COLUMN exdates TIMESTAMP[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,52 @@
+-- recurrences now hold everything needed to compute their occurrences on read,
+-- and schedules only keep individual events and edited occurrences
+ALTER TABLE recurrences
+    ADD COLUMN event_id BIGINT REFERENCES events(id) ON DELETE CASCADE,
+    ADD COLUMN start_time TIMESTAMP,
+    ADD COLUMN end_time TIMESTAMP,
+    ALTER COLUMN repetitions DROP NOT NULL,
+    ALTER COLUMN end_date DROP NOT NULL;
+
+UPDATE recurrences SET
+    event_id   = first_schedules.event_id,
+    start_time = COALESCE(recurrences.dtstart, first_schedules.start_time),
+    end_time   = COALESCE(recurrences.dtstart + recurrences.duration * INTERVAL '1 minute', first_schedules.end_time)
+FROM (
+    SELECT DISTINCT ON (recurrence_id) recurrence_id, event_id, start_time, end_time
+    FROM schedules
+    WHERE recurrence_id IS NOT NULL
+    ORDER BY recurrence_id, start_time
+) AS first_schedules
+WHERE first_schedules.recurrence_id = recurrences.id;
+
+DELETE FROM recurrences WHERE event_id IS NULL;
+
+-- materialized occurrences are set aside until the API tells the deleted and
+-- the edited ones apart, against what the recurrences generate, and keeps them
+-- as exdates and edited occurrences
+CREATE TABLE IF NOT EXISTS legacy_schedules
+(
+    id BIGINT PRIMARY KEY,
+    event_id BIGINT NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    recurrence_id BIGINT NOT NULL REFERENCES recurrences(id) ON DELETE CASCADE,
+    start_time TIMESTAMP NOT NULL,
+    end_time TIMESTAMP NOT NULL
+);
+
+INSERT INTO legacy_schedules (id, event_id, recurrence_id, start_time, end_time)
+SELECT id, event_id, recurrence_id, start_time, end_time FROM schedules WHERE recurrence_id IS NOT NULL;
+
+DELETE FROM schedules WHERE recurrence_id IS NOT NULL;
+
+ALTER TABLE recurrences
+    ALTER COLUMN event_id SET NOT NULL,
+    ALTER COLUMN start_time SET NOT NULL,
+    ALTER COLUMN end_time SET NOT NULL,
+    DROP COLUMN dtstart,
+    DROP COLUMN duration;
+
+ALTER TABLE schedules
+    ADD COLUMN original_start_time TIMESTAMP,
+    ADD CONSTRAINT schedules_original_start_time_check
+        CHECK ((recurrence_id IS NULL) = (original_start_time IS NULL)),
+    ADD UNIQUE (recurrence_id, original_start_time);
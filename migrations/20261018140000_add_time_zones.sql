@@ -0,0 +1,25 @@
+-- existing times were written as UTC wall-clock times
+SET LOCAL TimeZone = 'UTC';
+
+ALTER TABLE events
+    ADD COLUMN time_zone VARCHAR(64) NOT NULL DEFAULT 'UTC';
+
+ALTER TABLE schedules
+    ALTER COLUMN start_time TYPE TIMESTAMPTZ,
+    ALTER COLUMN end_time TYPE TIMESTAMPTZ,
+    ALTER COLUMN original_start_time TYPE TIMESTAMPTZ;
+
+ALTER TABLE legacy_schedules
+    ALTER COLUMN start_time TYPE TIMESTAMPTZ,
+    ALTER COLUMN end_time TYPE TIMESTAMPTZ;
+
+ALTER TABLE recurrences
+    ALTER COLUMN start_time TYPE TIMESTAMPTZ,
+    ALTER COLUMN end_time TYPE TIMESTAMPTZ,
+    ALTER COLUMN end_date TYPE TIMESTAMPTZ,
+    ALTER COLUMN exdates DROP DEFAULT,
+    ALTER COLUMN exdates TYPE TIMESTAMPTZ[],
+    ALTER COLUMN exdates SET DEFAULT '{}';
+
+ALTER TABLE events_comments
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ;
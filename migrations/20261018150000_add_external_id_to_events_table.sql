@@ -0,0 +1,3 @@
+ALTER TABLE events ADD COLUMN external_id TEXT;
+
+CREATE INDEX events_external_id_idx ON events (external_id);
@@ -0,0 +1,16 @@
+CREATE TYPE delivery_status AS ENUM (
+    'pending', 'sent', 'failed'
+);
+
+CREATE TABLE IF NOT EXISTS reminder_deliveries
+(
+    id BIGSERIAL PRIMARY KEY,
+    reminder_id BIGINT NOT NULL REFERENCES reminders(id) ON DELETE CASCADE,
+    occurrence_start_time TIMESTAMPTZ NOT NULL,
+    status delivery_status NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 1,
+    last_error TEXT,
+    claimed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMPTZ,
+    UNIQUE (reminder_id, occurrence_start_time)
+);
@@ -0,0 +1,14 @@
+CREATE TYPE participation_status AS ENUM (
+    'needs_action', 'accepted', 'declined', 'tentative'
+);
+
+ALTER TABLE users_events
+    ADD COLUMN status participation_status NOT NULL DEFAULT 'needs_action',
+    ADD COLUMN responded_at TIMESTAMPTZ,
+    ADD COLUMN comment VARCHAR(255);
+
+-- when confirmations were given is unknown
+UPDATE users_events SET status = 'accepted' WHERE confirmation;
+
+ALTER TABLE users_events
+    DROP COLUMN confirmation;
@@ -0,0 +1,20 @@
+-- answers of participants to single occurrences, overriding their status in
+-- the event; occurrences of recurrences are kept by their original start time
+-- so the answers survive edits of the occurrence
+CREATE TABLE IF NOT EXISTS occurrence_responses
+(
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    event_id BIGINT NOT NULL,
+    schedule_id BIGINT REFERENCES schedules(id) ON DELETE CASCADE,
+    recurrence_id BIGINT REFERENCES recurrences(id) ON DELETE CASCADE,
+    original_start_time TIMESTAMPTZ,
+    status participation_status NOT NULL,
+    comment VARCHAR(255),
+    responded_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (user_id, event_id) REFERENCES users_events(user_id, event_id) ON DELETE CASCADE,
+    CHECK ((schedule_id IS NULL) <> (recurrence_id IS NULL)),
+    CHECK ((recurrence_id IS NULL) = (original_start_time IS NULL)),
+    UNIQUE (user_id, schedule_id),
+    UNIQUE (user_id, recurrence_id, original_start_time)
+);
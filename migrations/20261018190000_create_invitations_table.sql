@@ -0,0 +1,22 @@
+CREATE TYPE invitation_status AS ENUM (
+    'pending', 'accepted', 'declined', 'revoked'
+);
+
+-- invitees are either users or email addresses of people without an account,
+-- bound to the user that answers once accepted
+CREATE TABLE IF NOT EXISTS invitations
+(
+    id BIGSERIAL PRIMARY KEY,
+    event_id BIGINT NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    inviter_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    user_id BIGINT REFERENCES users(id) ON DELETE CASCADE,
+    email VARCHAR(255),
+    status invitation_status NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    responded_at TIMESTAMPTZ,
+    CHECK (user_id IS NOT NULL OR email IS NOT NULL)
+);
+
+CREATE UNIQUE INDEX invitations_pending_user_key ON invitations (event_id, user_id) WHERE status = 'pending';
+CREATE UNIQUE INDEX invitations_pending_email_key ON invitations (event_id, email) WHERE status = 'pending';
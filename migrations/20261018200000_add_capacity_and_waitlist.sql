@@ -0,0 +1,15 @@
+-- declined participants don't take places
+ALTER TABLE events
+    ADD COLUMN capacity INTEGER CHECK (capacity > 0);
+
+-- users waiting for a place, promoted in the order they joined with the
+-- status they joined with
+CREATE TABLE IF NOT EXISTS waitlist
+(
+    id BIGSERIAL PRIMARY KEY,
+    event_id BIGINT NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status participation_status NOT NULL DEFAULT 'needs_action',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (event_id, user_id)
+);
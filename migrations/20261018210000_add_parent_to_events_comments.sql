@@ -0,0 +1,5 @@
+-- replies belong to the event of the comment they answer
+ALTER TABLE events_comments
+    ADD COLUMN parent_comment_id BIGINT REFERENCES events_comments(id) ON DELETE CASCADE;
+
+CREATE INDEX events_comments_event_id_parent_comment_id_idx ON events_comments (event_id, parent_comment_id);
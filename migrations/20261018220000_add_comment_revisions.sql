@@ -0,0 +1,17 @@
+-- deleted comments stay as tombstones, without their text, so their replies
+-- keep their place in threads
+ALTER TABLE events_comments
+    ADD COLUMN edited_at TIMESTAMPTZ,
+    ADD COLUMN deleted_at TIMESTAMPTZ;
+
+-- the versions edits replaced, with when each was written
+CREATE TABLE IF NOT EXISTS events_comments_revisions
+(
+    id BIGSERIAL PRIMARY KEY,
+    comment_id BIGINT NOT NULL REFERENCES events_comments(id) ON DELETE CASCADE,
+    title VARCHAR(255) NOT NULL,
+    content TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX events_comments_revisions_comment_id_idx ON events_comments_revisions (comment_id);
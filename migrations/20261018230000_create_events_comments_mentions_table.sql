@@ -0,0 +1,15 @@
+-- the contact notifications that go to a single one are sent to
+ALTER TABLE users_contacts
+    ADD COLUMN preferred BOOLEAN NOT NULL DEFAULT false;
+
+CREATE UNIQUE INDEX users_contacts_preferred_key ON users_contacts (user_id) WHERE preferred;
+
+CREATE TABLE IF NOT EXISTS events_comments_mentions
+(
+    comment_id BIGINT NOT NULL REFERENCES events_comments(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (comment_id, user_id)
+);
+
+CREATE INDEX events_comments_mentions_user_id_idx ON events_comments_mentions (user_id);
@@ -0,0 +1,9 @@
+-- emoji or short names like +1, once per user
+CREATE TABLE IF NOT EXISTS events_comments_reactions
+(
+    comment_id BIGINT NOT NULL REFERENCES events_comments(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    emoji VARCHAR(32) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (comment_id, user_id, emoji)
+);
@@ -0,0 +1,17 @@
+-- files of events, or of one of their comments, kept in the configured
+-- storage under the id of their row
+CREATE TABLE IF NOT EXISTS attachments
+(
+    id BIGSERIAL PRIMARY KEY,
+    event_id BIGINT NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    comment_id BIGINT REFERENCES events_comments(id) ON DELETE CASCADE,
+    uploader_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    name VARCHAR(255) NOT NULL,
+    mime_type VARCHAR(255) NOT NULL,
+    size BIGINT NOT NULL CHECK (size >= 0),
+    sha256 CHAR(64) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX attachments_event_id_idx ON attachments (event_id);
+CREATE INDEX attachments_comment_id_idx ON attachments (comment_id);
@@ -0,0 +1,327 @@
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use serde_json::{json, Value};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use crate::{
+    helpers::{
+        auth::CurrentUser,
+        error::ApiError,
+        extract::{Json, Multipart, Path},
+        policy::{self, Action},
+        validation,
+    },
+    storage::SharedStorage,
+};
+
+/// Largest file that can be uploaded, with some room for the rest of the
+/// multipart body in the request limit.
+pub const MAX_ATTACHMENT_BYTES: usize = 25 * 1024 * 1024;
+pub const MAX_UPLOAD_BYTES: usize = MAX_ATTACHMENT_BYTES + 64 * 1024;
+
+/// Types downloads are served with as uploaded. Any other, like HTML or SVG,
+/// could run scripts on this origin if a browser rendered it, so it's served
+/// as `application/octet-stream`.
+const SAFE_MIME_TYPES: &[&str] = &[
+    "application/pdf",
+    "application/zip",
+    "audio/mpeg",
+    "audio/ogg",
+    "image/gif",
+    "image/jpeg",
+    "image/png",
+    "image/webp",
+    "text/calendar",
+    "text/csv",
+    "text/plain",
+    "video/mp4",
+    "video/webm",
+];
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct Attachment
+{
+    pub id         : i64,
+    pub event_id   : i64,
+    pub comment_id : Option<i64>,
+    pub uploader_id: Option<i64>,
+    pub name       : String,
+    pub mime_type  : String,
+    pub size       : i64,
+    pub sha256     : String,
+    pub created_at : DateTime<Utc>,
+}
+
+/// Storage failures are logged, clients only learn something went wrong.
+fn storage_error(e: String) -> ApiError
+{
+    eprintln!("Storage error: {e}");
+
+    ApiError::Internal
+}
+
+/// Removes the contents of attachments whose rows are already deleted. Files
+/// left behind are only logged, as their rows are gone.
+pub async fn delete_files(storage: &SharedStorage, ids: &[i64])
+{
+    for id in ids
+    {
+        if let Err(e) = storage.delete(&id.to_string()).await
+        {
+            eprintln!("Storage error: {e}");
+        }
+    }
+}
+
+struct Upload
+{
+    name     : String,
+    mime_type: String,
+    bytes    : Bytes,
+}
+
+/// Reads the `file` field of a multipart body, with the name and type the
+/// client gave it.
+async fn read_upload(mut multipart: Multipart) -> Result<Upload, ApiError>
+{
+    let file_error = |code, message| ApiError::Validation(validation::field_error("file", validation::error(code, message)));
+
+    while let Some(field) = multipart.0.next_field().await?
+    {
+        if field.name() != Some("file")
+        {
+            continue;
+        }
+
+        let name      = field.file_name().unwrap_or_default().to_owned();
+        let mime_type = field.content_type().unwrap_or("application/octet-stream").to_owned();
+
+        if name.is_empty() || name.chars().count() > 255
+        {
+            return Err(file_error("name", "must have a name of at most 255 characters"));
+        }
+
+        if mime_type.chars().count() > 255
+        {
+            return Err(file_error("mime_type", "must have a type of at most 255 characters"));
+        }
+
+        let bytes = field.bytes().await?;
+
+        if bytes.len() > MAX_ATTACHMENT_BYTES
+        {
+            return Err(ApiError::PayloadTooLarge("Files can have at most 25 MiB.".into()));
+        }
+
+        return Ok(Upload { name, mime_type, bytes });
+    }
+
+    Err(file_error("required", "must be given"))
+}
+
+/// Keeps the metadata and then the contents, which are only committed
+/// together: the contents go when the metadata can't be committed.
+async fn save_upload(
+    db_pool: &PgPool,
+    storage: &SharedStorage,
+    event_id: i64,
+    comment_id: Option<i64>,
+    uploader_id: i64,
+    upload: Upload,
+) -> Result<Attachment, ApiError>
+{
+    let sha256 = format!("{:x}", Sha256::digest(&upload.bytes));
+
+    let mut transaction = db_pool.begin().await?;
+
+    let attachment = sqlx::query_as::<_, Attachment>
+        ("INSERT INTO attachments (event_id, comment_id, uploader_id, name, mime_type, size, sha256)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING *")
+        .bind(event_id)
+        .bind(comment_id)
+        .bind(uploader_id)
+        .bind(&upload.name)
+        .bind(&upload.mime_type)
+        .bind(upload.bytes.len() as i64)
+        .bind(&sha256)
+        .fetch_one(&mut *transaction)
+        .await?;
+
+    storage.put(&attachment.id.to_string(), &upload.bytes)
+        .await
+        .map_err(storage_error)?;
+
+    if let Err(e) = transaction.commit().await
+    {
+        delete_files(storage, &[attachment.id]).await;
+
+        return Err(e.into());
+    }
+
+    Ok(attachment)
+}
+
+fn created(attachment: Attachment) -> (StatusCode, Json<Value>)
+{
+    (
+        StatusCode::CREATED,
+        Json(json!({
+            "message": "File attached successfully.",
+            "id"     : attachment.id,
+            "sha256" : attachment.sha256,
+        })),
+    )
+}
+
+/// Participants attach files, like agendas or slides, in a `file` field.
+pub async fn attach_to_event(
+    State(db_pool): State<PgPool>,
+    State(storage): State<SharedStorage>,
+    CurrentUser(user): CurrentUser,
+    Path(event_id): Path<i64>,
+    multipart: Multipart,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    policy::authorize_event(&db_pool, user.id, event_id, Action::Attach).await?;
+
+    let upload     = read_upload(multipart).await?;
+    let attachment = save_upload(&db_pool, &storage, event_id, None, user.id, upload).await?;
+
+    Ok(created(attachment))
+}
+
+/// Only authors attach files to their comments, while they take part in the
+/// event.
+pub async fn attach_to_comment(
+    State(db_pool): State<PgPool>,
+    State(storage): State<SharedStorage>,
+    CurrentUser(user): CurrentUser,
+    Path(comment_id): Path<i64>,
+    multipart: Multipart,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    let event_id = policy::authorize_comment(&db_pool, user.id, comment_id, Action::EditComment).await?;
+
+    policy::authorize_event(&db_pool, user.id, event_id, Action::Attach).await?;
+
+    let upload     = read_upload(multipart).await?;
+    let attachment = save_upload(&db_pool, &storage, event_id, Some(comment_id), user.id, upload).await?;
+
+    Ok(created(attachment))
+}
+
+/// Files of the event and of its comments, for its participants.
+pub async fn list_event_attachments(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path(event_id): Path<i64>,
+) -> Result<(StatusCode, Json<Vec<Attachment>>), ApiError>
+{
+    policy::authorize_event(&db_pool, user.id, event_id, Action::Download).await?;
+
+    let attachments = sqlx::query_as::<_, Attachment>
+        ("SELECT * FROM attachments WHERE event_id = $1 ORDER BY id")
+        .bind(event_id)
+        .fetch_all(&db_pool)
+        .await?;
+
+    Ok((StatusCode::OK, Json(attachments)))
+}
+
+async fn find_attachment(db_pool: &PgPool, id: i64) -> Result<Attachment, ApiError>
+{
+    sqlx::query_as::<_, Attachment>("SELECT * FROM attachments WHERE id = $1")
+        .bind(id)
+        .fetch_optional(db_pool)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Attachment not found.".into()))
+}
+
+/// The type a download is served with, the uploaded one when it's safe.
+fn served_mime_type(mime_type: &str) -> String
+{
+    let essence = mime_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+
+    match SAFE_MIME_TYPES.contains(&essence.as_str())
+    {
+        true  => mime_type.to_owned(),
+        false => "application/octet-stream".to_owned(),
+    }
+}
+
+/// Answers the contents with the name they were uploaded with, and their type
+/// when it's safe to, never letting browsers guess another.
+pub async fn download_attachment(
+    State(db_pool): State<PgPool>,
+    State(storage): State<SharedStorage>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> Result<Response, ApiError>
+{
+    let attachment = find_attachment(&db_pool, id).await?;
+
+    policy::authorize_event(&db_pool, user.id, attachment.event_id, Action::Download).await?;
+
+    let bytes = storage.get(&attachment.id.to_string())
+        .await
+        .map_err(storage_error)?;
+
+    let disposition = format!(
+        "attachment; filename=\"{}\"",
+        attachment.name.replace(['"', '\\'], "_").replace(|c: char| c.is_control(), "_")
+    );
+
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, served_mime_type(&attachment.mime_type)),
+            (header::CONTENT_DISPOSITION, disposition),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_owned()),
+        ],
+        bytes,
+    )
+        .into_response())
+}
+
+/// Uploaders remove their files while they take part in the event, and owners
+/// remove any.
+pub async fn delete_attachment(
+    State(db_pool): State<PgPool>,
+    State(storage): State<SharedStorage>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    let attachment = find_attachment(&db_pool, id).await?;
+
+    let action = match attachment.uploader_id == Some(user.id)
+    {
+        true  => Action::Attach,
+        false => Action::Edit,
+    };
+
+    policy::authorize_event(&db_pool, user.id, attachment.event_id, action).await?;
+
+    let result = sqlx::query!("DELETE FROM attachments WHERE id = $1", id)
+        .execute(&db_pool)
+        .await?;
+
+    if result.rows_affected() == 0
+    {
+        return Err(ApiError::NotFound("Attachment not found.".into()));
+    }
+
+    delete_files(&storage, &[id]).await;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"message": "Attachment deleted successfully."})),
+    ))
+}
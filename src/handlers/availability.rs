@@ -0,0 +1,585 @@
+use std::collections::HashMap;
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveTime, Utc};
+use chrono_tz::Tz;
+use validator::{Validate, ValidationErrors};
+use crate::{
+    handlers::{
+        calendar::calendar_response,
+        event::ScheduleWindow,
+        participation::{ParticipationStatus, Responses},
+    },
+    helpers::{
+        error::ApiError,
+        extract::{Json, ValidJson},
+        ical::{self, ICalendar},
+        recurrence::{self, Weekday},
+        validation,
+    },
+};
+
+/// Minutes between candidate slots when not given.
+const DEFAULT_SLOT_STEP: i64 = 15;
+
+/// Candidate slots answered when not given.
+const DEFAULT_SLOT_LIMIT: usize = 10;
+
+/// A `[start, end)` interval.
+pub type Interval = (DateTime<Utc>, DateTime<Utc>);
+
+/// Sorts and merges overlapping or adjacent intervals.
+pub fn merge_intervals(mut intervals: Vec<Interval>) -> Vec<Interval>
+{
+    intervals.sort_unstable();
+
+    let mut merged: Vec<Interval> = Vec::with_capacity(intervals.len());
+
+    for (start, end) in intervals
+    {
+        match merged.last_mut()
+        {
+            Some((_, last_end)) if start <= *last_end => *last_end = (*last_end).max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+/// Merged busy intervals of each of the users in the `[from, to)` window,
+/// clipped to it, from the schedules they didn't decline, by themselves or
+/// along with the rest of their event.
+pub async fn busy_intervals(
+    db_pool: &PgPool,
+    user_ids: &[i64],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    confirmed_only: bool,
+) -> Result<HashMap<i64, Vec<Interval>>, sqlx::Error>
+{
+    let mut connection = db_pool.acquire().await?;
+
+    let responses = Responses::load(&mut connection, user_ids).await?;
+
+    let mut user_intervals: HashMap<i64, Vec<Interval>> = user_ids.iter().map(|id| (*id, Vec::new())).collect();
+
+    for schedule in recurrence::list_schedules(&mut connection, &responses.event_ids(), from, to).await?
+    {
+        let start = schedule.start_time.with_timezone(&Utc).max(from);
+        let end   = schedule.end_time.with_timezone(&Utc).min(to);
+
+        for (user_id, status) in responses.statuses(&schedule)
+        {
+            let busy = match status
+            {
+                ParticipationStatus::Accepted => true,
+                ParticipationStatus::Declined => false,
+                _ => !confirmed_only,
+            };
+
+            if busy
+            {
+                user_intervals.entry(user_id).or_default().push((start, end));
+            }
+        }
+    }
+
+    Ok(user_intervals.into_iter()
+        .map(|(user_id, intervals)| (user_id, merge_intervals(intervals)))
+        .collect())
+}
+
+/// Fails with the ids of the users that don't exist.
+pub async fn check_users_exist(db_pool: &PgPool, user_ids: &[i64]) -> Result<(), ApiError>
+{
+    let existing = sqlx::query_scalar!("SELECT id FROM users WHERE id = ANY($1)", user_ids)
+        .fetch_all(db_pool)
+        .await?;
+
+    let missing: Vec<String> = user_ids.iter()
+        .filter(|id| !existing.contains(id))
+        .map(i64::to_string)
+        .collect();
+
+    match missing.is_empty()
+    {
+        true  => Ok(()),
+        false => Err(ApiError::NotFound(format!("Users not found: {}.", missing.join(", ")))),
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct FreeBusyRequest
+{
+    #[validate(
+        length(min = 1, max = 100, message = "must have between 1 and 100 users"),
+        custom(function = "validation::unique_ids"),
+    )]
+    pub users         : Vec<i64>,
+    #[serde(flatten)]
+    pub window        : ScheduleWindow,
+    /// Leaves out the events the users didn't accept.
+    #[serde(default)]
+    pub confirmed_only: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BusyInterval
+{
+    pub start: DateTime<FixedOffset>,
+    pub end  : DateTime<FixedOffset>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserFreeBusy
+{
+    pub user_id: i64,
+    pub busy   : Vec<BusyInterval>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FreeBusy
+{
+    pub from : DateTime<FixedOffset>,
+    pub to   : DateTime<FixedOffset>,
+    pub users: Vec<UserFreeBusy>,
+}
+
+#[derive(FromRow)]
+struct FreeBusyUser
+{
+    id   : i64,
+    name : String,
+    email: Option<String>,
+}
+
+/// One VFREEBUSY per user, with its busy periods in UTC.
+async fn render_free_busy(
+    db_pool: &PgPool,
+    free_busy: &[(i64, Vec<Interval>)],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<String, sqlx::Error>
+{
+    let user_ids: Vec<i64> = free_busy.iter().map(|(user_id, _)| *user_id).collect();
+
+    let users: HashMap<i64, FreeBusyUser> = sqlx::query_as::<_, FreeBusyUser>
+        ("SELECT users.id, users.name,
+            (SELECT contact FROM users_contacts
+            WHERE users_contacts.user_id = users.id AND users_contacts.type = 'email'
+            ORDER BY users_contacts.id LIMIT 1) AS email
+        FROM users WHERE id = ANY($1)")
+        .bind(&user_ids)
+        .fetch_all(db_pool)
+        .await?
+        .into_iter()
+        .map(|user| (user.id, user))
+        .collect();
+
+    let stamp = Utc::now();
+    let mut calendar = ICalendar::new();
+
+    for (user_id, intervals) in free_busy
+    {
+        calendar.begin("VFREEBUSY");
+        calendar.property("UID", &format!("freebusy-{user_id}-{}@{}", stamp.timestamp(), ical::UID_DOMAIN));
+        calendar.property("DTSTAMP", &ical::format_utc(stamp));
+        calendar.property("DTSTART", &ical::format_utc(from));
+        calendar.property("DTEND", &ical::format_utc(to));
+
+        if let Some(user) = users.get(user_id)
+        {
+            calendar.property(
+                &format!("ATTENDEE;CN={}", ical::quote_param(&user.name)),
+                &ical::calendar_address(user.id, user.email.as_deref()),
+            );
+        }
+
+        if !intervals.is_empty()
+        {
+            let periods: Vec<String> = intervals.iter()
+                .map(|(start, end)| format!("{}/{}", ical::format_utc(*start), ical::format_utc(*end)))
+                .collect();
+
+            calendar.property("FREEBUSY;FBTYPE=BUSY", &periods.join(","));
+        }
+
+        calendar.end("VFREEBUSY");
+    }
+
+    Ok(calendar.finish())
+}
+
+/// Busy blocks of each user in a window, without anything about the events
+/// behind them, so private events can be counted too. Answers with VFREEBUSY
+/// components when `text/calendar` is accepted.
+pub async fn free_busy(
+    State(db_pool): State<PgPool>,
+    headers: HeaderMap,
+    ValidJson(req): ValidJson<FreeBusyRequest>,
+) -> Result<Response, ApiError>
+{
+    let (from, to) = req.window.bounds()?;
+    let time_zone  = req.window.time_zone();
+
+    check_users_exist(&db_pool, &req.users).await?;
+
+    let mut intervals = busy_intervals(&db_pool, &req.users, from, to, req.confirmed_only).await?;
+
+    let free_busy: Vec<(i64, Vec<Interval>)> = req.users.iter()
+        .map(|user_id| (*user_id, intervals.remove(user_id).unwrap_or_default()))
+        .collect();
+
+    let accepts_calendar = headers.get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/calendar"));
+
+    if accepts_calendar
+    {
+        return Ok(calendar_response(render_free_busy(&db_pool, &free_busy, from, to).await?));
+    }
+
+    let in_time_zone = |date_time: DateTime<Utc>| date_time.with_timezone(&time_zone).fixed_offset();
+
+    Ok((
+        StatusCode::OK,
+        Json(FreeBusy {
+            from : in_time_zone(from),
+            to   : in_time_zone(to),
+            users: free_busy.into_iter()
+                .map(|(user_id, intervals)| UserFreeBusy {
+                    user_id,
+                    busy: intervals.into_iter()
+                        .map(|(start, end)| BusyInterval { start: in_time_zone(start), end: in_time_zone(end) })
+                        .collect(),
+                })
+                .collect(),
+        }),
+    )
+        .into_response())
+}
+
+/// Local hours in which meetings may be placed, every day or only some.
+#[derive(Deserialize)]
+pub struct WorkingHours
+{
+    pub start       : NaiveTime,
+    pub end         : NaiveTime,
+    /// Every day when empty.
+    #[serde(default)]
+    pub days_of_week: Vec<Weekday>,
+}
+
+impl Validate for WorkingHours
+{
+    fn validate(&self) -> Result<(), ValidationErrors>
+    {
+        match self.end > self.start
+        {
+            true  => Ok(()),
+            false => Err(validation::field_error("end", validation::error("time_range", "must be after start"))),
+        }
+    }
+}
+
+impl WorkingHours
+{
+    /// Whether a slot fits in the working hours of the day it starts on.
+    fn contain(&self, start: DateTime<Utc>, end: DateTime<Utc>, time_zone: &Tz) -> bool
+    {
+        let start = start.with_timezone(time_zone);
+        let end   = end.with_timezone(time_zone);
+
+        (self.days_of_week.is_empty() || self.days_of_week.contains(&start.weekday().into()))
+            && start.date_naive() == end.date_naive()
+            && start.time() >= self.start
+            && end.time() <= self.end
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct FindSlotsRequest
+{
+    #[validate(
+        length(min = 1, max = 100, message = "must have between 1 and 100 users"),
+        custom(function = "validation::unique_ids"),
+    )]
+    pub users         : Vec<i64>,
+    /// Minutes.
+    #[validate(range(min = 1, max = 1440, message = "must be between 1 and 1440 minutes"))]
+    pub duration      : i64,
+    #[serde(flatten)]
+    pub window        : ScheduleWindow,
+    /// Read in the time zone of the window.
+    #[validate(nested)]
+    pub working_hours : Option<WorkingHours>,
+    /// How many of the users must be free, all of them by default.
+    #[validate(range(min = 1, message = "must be at least 1"))]
+    pub quorum        : Option<usize>,
+    /// Minutes between the starts of consecutive candidates.
+    #[validate(range(min = 5, max = 1440, message = "must be between 5 and 1440 minutes"))]
+    pub step          : Option<i64>,
+    #[validate(range(min = 1, max = 100, message = "must be between 1 and 100"))]
+    pub limit         : Option<usize>,
+    #[serde(default)]
+    pub confirmed_only: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Slot
+{
+    pub start      : DateTime<FixedOffset>,
+    pub end        : DateTime<FixedOffset>,
+    pub available  : Vec<i64>,
+    pub unavailable: Vec<i64>,
+}
+
+/// Whether an interval overlaps any of some sorted and merged intervals.
+fn overlaps(intervals: &[Interval], start: DateTime<Utc>, end: DateTime<Utc>) -> bool
+{
+    let index = intervals.partition_point(|(_, interval_end)| *interval_end <= start);
+
+    intervals.get(index).is_some_and(|(interval_start, _)| *interval_start < end)
+}
+
+/// Candidate slots where at least a quorum of the users is free, the ones
+/// more users can attend first, and then the earliest.
+pub async fn find_slots(
+    State(db_pool): State<PgPool>,
+    ValidJson(req): ValidJson<FindSlotsRequest>,
+) -> Result<(StatusCode, Json<Vec<Slot>>), ApiError>
+{
+    let (from, to) = req.window.bounds()?;
+    let time_zone  = req.window.time_zone();
+    let quorum     = req.quorum.unwrap_or(req.users.len());
+
+    if quorum > req.users.len()
+    {
+        return Err(ApiError::Validation(validation::field_error(
+            "quorum",
+            validation::error("range", "must not be more than the number of users"),
+        )));
+    }
+
+    check_users_exist(&db_pool, &req.users).await?;
+
+    let intervals = busy_intervals(&db_pool, &req.users, from, to, req.confirmed_only).await?;
+
+    Ok((StatusCode::OK, Json(search_slots(&req, &intervals, from, to, &time_zone, quorum))))
+}
+
+/// The slots of the `[from, to)` window where at least `quorum` of the users
+/// have no busy interval, sorted and limited as `find_slots` answers them.
+fn search_slots(
+    req: &FindSlotsRequest,
+    intervals: &HashMap<i64, Vec<Interval>>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    time_zone: &Tz,
+    quorum: usize,
+) -> Vec<Slot>
+{
+    let duration = Duration::minutes(req.duration);
+    let step     = Duration::minutes(req.step.unwrap_or(DEFAULT_SLOT_STEP));
+
+    // Candidates start on multiples of the step, counted from the epoch.
+    let step_seconds = step.num_seconds();
+    let mut start    = DateTime::from_timestamp(from.timestamp().div_euclid(step_seconds) * step_seconds, 0)
+        .unwrap_or(from);
+
+    if start < from
+    {
+        start += step;
+    }
+
+    let mut slots = Vec::new();
+
+    while start + duration <= to
+    {
+        let end = start + duration;
+
+        let in_working_hours = match &req.working_hours
+        {
+            Some(hours) => hours.contain(start, end, time_zone),
+            None        => true,
+        };
+
+        if in_working_hours
+        {
+            let (available, unavailable): (Vec<i64>, Vec<i64>) = req.users.iter()
+                .partition(|user_id| !intervals.get(user_id).is_some_and(|busy| overlaps(busy, start, end)));
+
+            if available.len() >= quorum
+            {
+                slots.push((start, end, available, unavailable));
+            }
+        }
+
+        start += step;
+    }
+
+    slots.sort_by(|(a_start, _, a_available, _), (b_start, _, b_available, _)|
+        b_available.len().cmp(&a_available.len()).then(a_start.cmp(b_start))
+    );
+    slots.truncate(req.limit.unwrap_or(DEFAULT_SLOT_LIMIT));
+
+    slots.into_iter()
+        .map(|(start, end, available, unavailable)| Slot {
+            start: start.with_timezone(time_zone).fixed_offset(),
+            end  : end.with_timezone(time_zone).fixed_offset(),
+            available,
+            unavailable,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use chrono::TimeZone;
+    use serde_json::json;
+    use super::*;
+
+    fn utc(day: u32, hour: u32, minute: u32) -> DateTime<Utc>
+    {
+        Utc.with_ymd_and_hms(2026, 11, day, hour, minute, 0).unwrap()
+    }
+
+    fn request(request: serde_json::Value) -> FindSlotsRequest
+    {
+        serde_json::from_value(request).unwrap()
+    }
+
+    fn starts(slots: &[Slot]) -> Vec<DateTime<Utc>>
+    {
+        slots.iter().map(|slot| slot.start.to_utc()).collect()
+    }
+
+    #[test]
+    fn merges_overlapping_and_adjacent_intervals()
+    {
+        let merged = merge_intervals(vec![
+            (utc(2, 13, 0), utc(2, 14, 0)),
+            (utc(2, 9, 0), utc(2, 10, 0)),
+            (utc(2, 9, 30), utc(2, 11, 0)),
+            (utc(2, 11, 0), utc(2, 12, 0)),
+            (utc(2, 9, 45), utc(2, 10, 15)),
+        ]);
+
+        assert_eq!(merged, vec![(utc(2, 9, 0), utc(2, 12, 0)), (utc(2, 13, 0), utc(2, 14, 0))]);
+        assert!(merge_intervals(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn tells_overlaps_from_touching_intervals()
+    {
+        let busy = [(utc(2, 9, 0), utc(2, 10, 0)), (utc(2, 12, 0), utc(2, 13, 0))];
+
+        assert!(!overlaps(&busy, utc(2, 8, 0), utc(2, 9, 0)));
+        assert!(!overlaps(&busy, utc(2, 10, 0), utc(2, 12, 0)));
+        assert!(overlaps(&busy, utc(2, 9, 59), utc(2, 10, 30)));
+        assert!(overlaps(&busy, utc(2, 11, 0), utc(2, 14, 0)));
+        assert!(!overlaps(&[], utc(2, 11, 0), utc(2, 14, 0)));
+    }
+
+    #[test]
+    fn finds_slots_where_everyone_is_free_by_default()
+    {
+        let req = request(json!({ "users": [1, 2], "duration": 60, "step": 30, "limit": 100 }));
+        let intervals = HashMap::from([
+            (1, vec![(utc(2, 9, 0), utc(2, 10, 0))]),
+            (2, vec![(utc(2, 10, 30), utc(2, 11, 0))]),
+        ]);
+
+        let slots = search_slots(&req, &intervals, utc(2, 8, 0), utc(2, 12, 0), &Tz::UTC, 2);
+
+        assert_eq!(starts(&slots), vec![utc(2, 8, 0), utc(2, 11, 0)]);
+        assert!(slots.iter().all(|slot| slot.available == [1, 2] && slot.unavailable.is_empty()));
+    }
+
+    #[test]
+    fn ranks_slots_by_attendance_above_the_quorum()
+    {
+        let req = request(json!({ "users": [1, 2, 3], "duration": 60, "step": 60, "limit": 100 }));
+        let intervals = HashMap::from([
+            (1, vec![(utc(2, 8, 0), utc(2, 10, 0))]),
+            (2, vec![(utc(2, 8, 0), utc(2, 9, 0))]),
+        ]);
+
+        let slots = search_slots(&req, &intervals, utc(2, 8, 0), utc(2, 11, 0), &Tz::UTC, 1);
+
+        assert_eq!(starts(&slots), vec![utc(2, 10, 0), utc(2, 9, 0), utc(2, 8, 0)]);
+        assert_eq!(slots[1].available, [2, 3]);
+        assert_eq!(slots[1].unavailable, [1]);
+        assert_eq!(slots[2].available, [3]);
+
+        // a quorum of every user but one leaves out the slot only one can attend
+        let slots = search_slots(&req, &intervals, utc(2, 8, 0), utc(2, 11, 0), &Tz::UTC, 2);
+        assert_eq!(starts(&slots), vec![utc(2, 10, 0), utc(2, 9, 0)]);
+
+        // nobody busy at all is no reason to find nothing
+        let slots = search_slots(&req, &HashMap::new(), utc(2, 8, 0), utc(2, 11, 0), &Tz::UTC, 3);
+        assert_eq!(slots.len(), 3);
+    }
+
+    #[test]
+    fn aligns_slots_to_the_step_and_limits_them()
+    {
+        let req = request(json!({ "users": [1], "duration": 30, "step": 15, "limit": 2 }));
+
+        let slots = search_slots(&req, &HashMap::new(), utc(2, 8, 7), utc(2, 10, 0), &Tz::UTC, 1);
+
+        assert_eq!(starts(&slots), vec![utc(2, 8, 15), utc(2, 8, 30)]);
+
+        let slots = search_slots(&req, &HashMap::new(), utc(2, 8, 7), utc(2, 8, 40), &Tz::UTC, 1);
+        assert!(slots.is_empty());
+    }
+
+    #[test]
+    fn keeps_slots_crossing_midnight_out_of_working_hours()
+    {
+        let time_zone: Tz = "America/Sao_Paulo".parse().unwrap();
+        let req = request(json!({
+            "users"        : [1],
+            "duration"     : 120,
+            "step"         : 60,
+            "limit"        : 100,
+            "working_hours": { "start": "00:00:00", "end": "23:59:59" },
+        }));
+
+        // 22:00 to 04:00 in São Paulo (UTC-3)
+        let (from, to) = (utc(3, 1, 0), utc(3, 7, 0));
+
+        let slots = search_slots(&req, &HashMap::new(), from, to, &time_zone, 1);
+        assert_eq!(starts(&slots), vec![utc(3, 3, 0), utc(3, 4, 0), utc(3, 5, 0)]);
+        assert_eq!(slots[0].start.to_rfc3339(), "2026-11-03T00:00:00-03:00");
+
+        let req = request(json!({ "users": [1], "duration": 120, "step": 60, "limit": 100 }));
+
+        let slots = search_slots(&req, &HashMap::new(), from, to, &time_zone, 1);
+        assert_eq!(starts(&slots), vec![utc(3, 1, 0), utc(3, 2, 0), utc(3, 3, 0), utc(3, 4, 0), utc(3, 5, 0)]);
+    }
+
+    #[test]
+    fn keeps_slots_to_the_working_days()
+    {
+        let req = request(json!({
+            "users"        : [1],
+            "duration"     : 60,
+            "step"         : 60,
+            "limit"        : 100,
+            "working_hours": { "start": "09:00:00", "end": "11:00:00", "days_of_week": ["monday"] },
+        }));
+
+        // from Sunday 1 to Tuesday 3
+        let slots = search_slots(&req, &HashMap::new(), utc(1, 0, 0), utc(4, 0, 0), &Tz::UTC, 1);
+
+        assert_eq!(starts(&slots), vec![utc(2, 9, 0), utc(2, 10, 0)]);
+    }
+}
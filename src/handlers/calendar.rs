@@ -0,0 +1,863 @@
+use std::collections::HashMap;
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use serde_json::{json, Value};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use crate::{
+    handlers::{
+        event::{self, CreateEventRequest, Event, EventConfiguration, TimeZoneQuery},
+        participation::ParticipationStatus,
+    },
+    helpers::{
+        auth::CurrentUser,
+        error::ApiError,
+        extract::{Json, Path, Query},
+        ical::{self, Component, ICalendar, Property},
+        policy::{self, Action},
+        recurrence::{self, Recurrence, Schedule},
+        time_zone::DateTimeInput,
+        validation,
+    },
+};
+use validator::{Validate, ValidationErrors};
+
+#[derive(FromRow)]
+struct EventTag
+{
+    event_id: i64,
+    name    : String,
+}
+
+#[derive(FromRow)]
+struct Attendee
+{
+    event_id    : i64,
+    id          : i64,
+    name        : String,
+    email       : Option<String>,
+    status      : ParticipationStatus,
+    owner       : bool,
+}
+
+#[derive(FromRow)]
+struct Alarm
+{
+    event_id      : i64,
+    minutes_before: i32,
+}
+
+/// What an event brings to the VEVENTs of its schedules and recurrences.
+struct EventProperties<'a>
+{
+    event    : &'a Event,
+    tags     : Vec<&'a str>,
+    attendees: Vec<&'a Attendee>,
+    alarms   : Vec<i32>,
+}
+
+fn group_by_event<T>(rows: Vec<T>, event_id: impl Fn(&T) -> i64) -> HashMap<i64, Vec<T>>
+{
+    let mut groups: HashMap<i64, Vec<T>> = HashMap::new();
+
+    for row in rows
+    {
+        groups.entry(event_id(&row)).or_default().push(row);
+    }
+
+    groups
+}
+
+fn write_event_properties(calendar: &mut ICalendar, properties: &EventProperties)
+{
+    let event = properties.event;
+
+    calendar.text("SUMMARY", &event.name);
+    calendar.text("DESCRIPTION", &event.description);
+    calendar.property("CLASS", if event.private { "PRIVATE" } else { "PUBLIC" });
+
+    if !properties.tags.is_empty()
+    {
+        let categories: Vec<String> = properties.tags.iter().map(|tag| ical::escape_text(tag)).collect();
+        calendar.property("CATEGORIES", &categories.join(","));
+    }
+
+    if let Some(organizer) = properties.attendees.iter().find(|attendee| attendee.owner)
+    {
+        calendar.property(
+            &format!("ORGANIZER;CN={}", ical::quote_param(&organizer.name)),
+            &ical::calendar_address(organizer.id, organizer.email.as_deref()),
+        );
+    }
+
+    for attendee in &properties.attendees
+    {
+        calendar.property(
+            &format!(
+                "ATTENDEE;CN={};ROLE=REQ-PARTICIPANT;PARTSTAT={}",
+                ical::quote_param(&attendee.name),
+                attendee.status.part_stat(),
+            ),
+            &ical::calendar_address(attendee.id, attendee.email.as_deref()),
+        );
+    }
+
+    for minutes_before in &properties.alarms
+    {
+        calendar.begin("VALARM");
+        calendar.property("ACTION", "DISPLAY");
+        calendar.text("DESCRIPTION", &event.name);
+        calendar.property("TRIGGER", &format!("-PT{minutes_before}M"));
+        calendar.end("VALARM");
+    }
+}
+
+fn utc(date_time: DateTime<FixedOffset>) -> DateTime<Utc>
+{
+    date_time.with_timezone(&Utc)
+}
+
+/// Writes a recurrence as a VEVENT with its RRULE and EXDATEs, followed by one
+/// VEVENT per edited occurrence, identified by its RECURRENCE-ID.
+fn write_recurrence(
+    calendar: &mut ICalendar,
+    properties: &EventProperties,
+    recurrence: &Recurrence,
+    overrides: &[&Schedule],
+    uid: &str,
+    stamp: DateTime<Utc>,
+) -> Result<(), sqlx::Error>
+{
+    let time_zone = recurrence.time_zone();
+
+    calendar.begin("VEVENT");
+    calendar.property("UID", uid);
+    calendar.property("DTSTAMP", &ical::format_utc(stamp));
+    calendar.date_time("DTSTART", utc(recurrence.start_time), &time_zone);
+    calendar.date_time("DTEND", utc(recurrence.end_time), &time_zone);
+    calendar.property("RRULE", &recurrence.ical_rrule()?);
+
+    if !recurrence.exdates.is_empty()
+    {
+        let exdates: Vec<DateTime<Utc>> = recurrence.exdates.iter().map(|exdate| utc(*exdate)).collect();
+        calendar.date_times("EXDATE", &exdates, &time_zone);
+    }
+
+    write_event_properties(calendar, properties);
+    calendar.end("VEVENT");
+
+    for schedule in overrides
+    {
+        calendar.begin("VEVENT");
+        calendar.property("UID", uid);
+        calendar.property("DTSTAMP", &ical::format_utc(stamp));
+
+        if let Some(original_start_time) = schedule.original_start_time
+        {
+            calendar.date_time("RECURRENCE-ID", utc(original_start_time), &time_zone);
+        }
+
+        calendar.date_time("DTSTART", utc(schedule.start_time), &time_zone);
+        calendar.date_time("DTEND", utc(schedule.end_time), &time_zone);
+        write_event_properties(calendar, properties);
+        calendar.end("VEVENT");
+    }
+
+    Ok(())
+}
+
+/// Serializes the given events, with their schedules, recurrences, tags and
+/// participants. Reminders become alarms: the ones of `user_id` only, or every
+/// distinct one of the events without it.
+async fn render_calendar(
+    db_pool: &PgPool,
+    event_ids: &[i64],
+    user_id: Option<i64>,
+    name: Option<&str>,
+) -> Result<String, sqlx::Error>
+{
+    let events = sqlx::query_as::<_, Event>
+        ("SELECT * FROM events WHERE id = ANY($1) ORDER BY id")
+        .bind(event_ids)
+        .fetch_all(db_pool)
+        .await?;
+
+    let tags = group_by_event(
+        sqlx::query_as::<_, EventTag>
+            ("SELECT events_tags.event_id::BIGINT AS event_id, tags.name FROM tags
+            JOIN events_tags ON events_tags.tag_id = tags.id
+            WHERE events_tags.event_id = ANY($1)
+            ORDER BY tags.name")
+            .bind(event_ids)
+            .fetch_all(db_pool)
+            .await?,
+        |tag| tag.event_id,
+    );
+
+    let attendees = group_by_event(
+        sqlx::query_as::<_, Attendee>
+            ("SELECT users_events.event_id, users.id, users.name, users_events.status, users_events.owner,
+                (SELECT contact FROM users_contacts
+                WHERE users_contacts.user_id = users.id AND users_contacts.type = 'email'
+                ORDER BY users_contacts.id LIMIT 1) AS email
+            FROM users
+            JOIN users_events ON users_events.user_id = users.id
+            WHERE users_events.event_id = ANY($1)
+            ORDER BY users_events.owner DESC, users.name")
+            .bind(event_ids)
+            .fetch_all(db_pool)
+            .await?,
+        |attendee| attendee.event_id,
+    );
+
+    let alarms = group_by_event(
+        sqlx::query_as::<_, Alarm>
+            ("SELECT DISTINCT reminders.event_id, reminders.minutes_before FROM reminders
+            JOIN users_contacts ON users_contacts.id = reminders.user_contact_id
+            WHERE reminders.event_id = ANY($1) AND ($2::BIGINT IS NULL OR users_contacts.user_id = $2)
+            ORDER BY reminders.minutes_before")
+            .bind(event_ids)
+            .bind(user_id)
+            .fetch_all(db_pool)
+            .await?,
+        |alarm| alarm.event_id,
+    );
+
+    let schedules = group_by_event(
+        sqlx::query_as::<_, Schedule>
+            ("SELECT id, recurrence_id, event_id, start_time, end_time, original_start_time
+            FROM schedules WHERE event_id = ANY($1)
+            ORDER BY start_time")
+            .bind(event_ids)
+            .fetch_all(db_pool)
+            .await?,
+        |schedule| schedule.event_id,
+    );
+
+    let recurrences = group_by_event(
+        recurrence::list_recurrences(db_pool, event_ids).await?,
+        |recurrence| recurrence.event_id,
+    );
+
+    let stamp = Utc::now();
+    let mut calendar = ICalendar::new();
+
+    if let Some(name) = name
+    {
+        calendar.text("X-WR-CALNAME", name);
+    }
+
+    for event in &events
+    {
+        let properties = EventProperties {
+            event,
+            tags     : tags.get(&event.id)
+                .map_or_else(Vec::new, |tags| tags.iter().map(|tag| tag.name.as_str()).collect()),
+            attendees: attendees.get(&event.id)
+                .map_or_else(Vec::new, |attendees| attendees.iter().collect()),
+            alarms   : alarms.get(&event.id)
+                .map_or_else(Vec::new, |alarms| alarms.iter().map(|alarm| alarm.minutes_before).collect()),
+        };
+
+        let schedules: Vec<&Schedule> = schedules.get(&event.id)
+            .map_or_else(Vec::new, |schedules| schedules.iter().collect());
+
+        let recurrences: Vec<&Recurrence> = recurrences.get(&event.id)
+            .map_or_else(Vec::new, |recurrences| recurrences.iter().collect());
+
+        let individual_schedules: Vec<&Schedule> = schedules.iter()
+            .filter(|schedule| schedule.recurrence_id.is_none())
+            .copied()
+            .collect();
+
+        // an imported event keeps its UID, as long as it's a single series
+        let external_uid = event.external_id.as_deref()
+            .filter(|_| recurrences.len() + individual_schedules.len() == 1);
+
+        for recurrence in recurrences
+        {
+            let overrides: Vec<&Schedule> = schedules.iter()
+                .filter(|schedule| schedule.recurrence_id == Some(recurrence.id))
+                .copied()
+                .collect();
+
+            let uid = external_uid.map_or_else(
+                || format!("event-{}-recurrence-{}@{}", event.id, recurrence.id, ical::UID_DOMAIN),
+                str::to_owned,
+            );
+
+            write_recurrence(&mut calendar, &properties, recurrence, &overrides, &uid, stamp)?;
+        }
+
+        let time_zone = event.time_zone();
+
+        for schedule in individual_schedules
+        {
+            let uid = external_uid.map_or_else(
+                || format!("event-{}-schedule-{}@{}", event.id, schedule.id.unwrap_or_default(), ical::UID_DOMAIN),
+                str::to_owned,
+            );
+
+            calendar.begin("VEVENT");
+            calendar.property("UID", &uid);
+            calendar.property("DTSTAMP", &ical::format_utc(stamp));
+            calendar.date_time("DTSTART", utc(schedule.start_time), &time_zone);
+            calendar.date_time("DTEND", utc(schedule.end_time), &time_zone);
+            write_event_properties(&mut calendar, &properties);
+            calendar.end("VEVENT");
+        }
+    }
+
+    Ok(calendar.finish())
+}
+
+pub fn calendar_response(calendar: String) -> Response
+{
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        calendar,
+    )
+        .into_response()
+}
+
+pub async fn export_event(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> Result<Response, ApiError>
+{
+    policy::authorize_event(&db_pool, user.id, id, Action::View).await?;
+
+    let calendar = render_calendar(&db_pool, &[id], None, None)
+        .await?;
+
+    Ok(calendar_response(calendar))
+}
+
+/// Serves `/events/:id` and `/events/:id.ics`, as a route parameter must span
+/// a whole path segment.
+pub async fn get_event(
+    state: State<PgPool>,
+    current_user: CurrentUser,
+    Path(id): Path<String>,
+    query: Query<TimeZoneQuery>,
+) -> Result<Response, ApiError>
+{
+    let (id, ics) = match id.strip_suffix(".ics")
+    {
+        Some(id) => (id, true),
+        None => (id.as_str(), false),
+    };
+
+    let id = id.parse::<i64>().map_err(|_| ApiError::BadRequest("Invalid event id.".into()))?;
+
+    if ics
+    {
+        export_event(state, current_user, Path(id)).await
+    }
+    else
+    {
+        event::get_event(state, current_user, Path(id), query).await.map(IntoResponse::into_response)
+    }
+}
+
+/// Leaves out the private events the current user doesn't take part in.
+pub async fn export_user_calendar(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path(user_id): Path<i64>,
+) -> Result<Response, ApiError>
+{
+    let user_name = sqlx::query_scalar!("SELECT name FROM users WHERE id = $1", user_id)
+        .fetch_optional(&db_pool)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found.".into()))?;
+
+    let event_ids = sqlx::query_scalar!("SELECT event_id FROM users_events WHERE user_id = $1", user_id)
+        .fetch_all(&db_pool)
+        .await?;
+
+    let event_ids = policy::visible_event_ids(&db_pool, user.id, &event_ids)
+        .await?;
+
+    let calendar = render_calendar(&db_pool, &event_ids, Some(user_id), Some(&user_name))
+        .await?;
+
+    Ok(calendar_response(calendar))
+}
+
+/// Color of the tags created for unknown CATEGORIES.
+const IMPORTED_TAG_COLOR: &str = "9E9E9E";
+
+/// Length of the `name` and `description` columns of `events`.
+const MAX_TEXT_LENGTH: usize = 255;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus
+{
+    Created,
+    Updated,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportItem
+{
+    pub uid     : Option<String>,
+    pub status  : ImportStatus,
+    pub event_id: Option<i64>,
+    pub message : Option<String>,
+}
+
+impl ImportItem
+{
+    fn new(uid: Option<&str>, status: ImportStatus, event_id: Option<i64>, message: Option<String>) -> Self
+    {
+        ImportItem { uid: uid.map(str::to_owned), status, event_id, message }
+    }
+}
+
+/// A VEVENT read into what `create_event` takes.
+struct ImportedEvent
+{
+    name         : String,
+    description  : String,
+    private      : bool,
+    time_zone    : Tz,
+    categories   : Vec<String>,
+    configuration: EventConfiguration,
+}
+
+/// Reported for a UID, with the internal details left out like in responses.
+fn error_message(error: impl Into<ApiError>) -> String
+{
+    error.into().to_string()
+}
+
+/// The errors of an imported event, the way `create_event` would report them.
+fn invalid_fields(errors: &ValidationErrors) -> String
+{
+    let fields: Vec<String> = validation::field_errors(errors)
+        .into_iter()
+        .flat_map(|(field, errors)| errors.into_iter()
+            .map(move |error| format!("{field} {}", error["message"].as_str().unwrap_or("is invalid"))))
+        .collect();
+
+    format!("Invalid event: {}.", fields.join(", "))
+}
+
+fn truncate(text: &str) -> String
+{
+    text.chars().take(MAX_TEXT_LENGTH).collect()
+}
+
+fn is_cancelled(vevent: &Component) -> bool
+{
+    vevent.property("STATUS").is_some_and(|status| status.value.eq_ignore_ascii_case("CANCELLED"))
+}
+
+/// Reads the DATE or DATE-TIME values of a property, in the zone of its TZID,
+/// in UTC when they end with `Z`, or else in the event time zone.
+fn property_times(property: &Property, time_zone: &Tz) -> Result<Vec<DateTime<Utc>>, String>
+{
+    let time_zone = match property.param("TZID")
+    {
+        Some(tzid) => tzid.trim_matches('"')
+            .parse::<Tz>()
+            .map_err(|_| format!("Unknown time zone {tzid}."))?,
+        None => *time_zone,
+    };
+
+    property.value.split(',')
+        .map(|value|
+        {
+            let (value, time_zone) = match value.strip_suffix('Z')
+            {
+                Some(value) => (value, Tz::UTC),
+                None => (value, time_zone),
+            };
+
+            let wall_clock = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+                .or_else(|_| NaiveDate::parse_from_str(value, "%Y%m%d")
+                    .map(|date| date.and_time(Default::default())))
+                .map_err(|_| format!("Invalid {} value {value}.", property.name))?;
+
+            DateTimeInput::WallClock(wall_clock)
+                .resolve(&time_zone)
+                .ok_or_else(|| format!("{} skipped by a DST transition in {time_zone}.", property.name))
+        })
+        .collect()
+}
+
+fn property_time(vevent: &Component, name: &str, time_zone: &Tz) -> Result<Option<DateTime<Utc>>, String>
+{
+    match vevent.property(name)
+    {
+        Some(property) => Ok(property_times(property, time_zone)?.first().copied()),
+        None => Ok(None),
+    }
+}
+
+/// Whether DTSTART is a DATE rather than a DATE-TIME, flagged or not.
+fn is_date(vevent: &Component) -> bool
+{
+    vevent.property("DTSTART").is_some_and(|dtstart| dtstart.param("VALUE")
+        .map_or(!dtstart.value.contains('T'), |value| value.eq_ignore_ascii_case("DATE")))
+}
+
+/// The start and end of a VEVENT, which may have a DURATION instead of a DTEND,
+/// or neither when it lasts a day (for dates) or no time at all (RFC 5545,
+/// section 3.6.1), which events here can't.
+fn vevent_times(vevent: &Component, time_zone: &Tz) -> Result<(DateTime<Utc>, DateTime<Utc>), String>
+{
+    let start_time = property_time(vevent, "DTSTART", time_zone)?.ok_or("Missing DTSTART.")?;
+
+    let end_time = match (property_time(vevent, "DTEND", time_zone)?, vevent.property("DURATION"))
+    {
+        (Some(end_time), _) => end_time,
+        (None, Some(duration)) => ical::parse_duration(&duration.value)
+            .and_then(|duration| start_time.checked_add_signed(duration))
+            .ok_or_else(|| format!("Invalid DURATION value {}.", duration.value))?,
+        (None, None) if is_date(vevent) => start_time + Duration::days(1),
+        (None, None) => start_time,
+    };
+
+    Ok((start_time, end_time))
+}
+
+fn parse_vevent(vevent: &Component) -> Result<ImportedEvent, String>
+{
+    let text = |name: &str| vevent.property(name).map(|property| ical::unescape_text(&property.value));
+
+    let time_zone = match vevent.property("DTSTART").and_then(|dtstart| dtstart.param("TZID"))
+    {
+        Some(tzid) => tzid.trim_matches('"')
+            .parse::<Tz>()
+            .map_err(|_| format!("Unknown time zone {tzid}."))?,
+        None => Tz::UTC,
+    };
+
+    let (start_time, end_time) = vevent_times(vevent, &time_zone)?;
+
+    let configuration = match vevent.property("RRULE")
+    {
+        Some(rrule) =>
+        {
+            let mut exdates = Vec::new();
+            for exdate in vevent.properties("EXDATE")
+            {
+                exdates.extend(property_times(exdate, &time_zone)?
+                    .into_iter()
+                    .map(|exdate| DateTimeInput::Instant(exdate.fixed_offset())));
+            }
+
+            EventConfiguration::Rule {
+                rrule   : rrule.value.clone(),
+                dtstart : DateTimeInput::Instant(start_time.fixed_offset()),
+                duration: i32::try_from((end_time - start_time).num_minutes())
+                    .map_err(|_| "The event lasts too long.".to_owned())?,
+                exdates : Some(exdates),
+            }
+        },
+        None => EventConfiguration::Individual {
+            start_time: DateTimeInput::Instant(start_time.fixed_offset()),
+            end_time  : DateTimeInput::Instant(end_time.fixed_offset()),
+        },
+    };
+
+    configuration.validate().map_err(|errors| invalid_fields(&errors))?;
+
+    Ok(ImportedEvent {
+        name       : truncate(&text("SUMMARY")
+            .filter(|summary| !summary.trim().is_empty())
+            .unwrap_or_else(|| "Untitled event".to_owned())),
+        description: truncate(&text("DESCRIPTION").unwrap_or_default()),
+        private    : vevent.property("CLASS")
+            .is_some_and(|class| !class.value.eq_ignore_ascii_case("PUBLIC")),
+        time_zone,
+        categories : vevent.properties("CATEGORIES")
+            .flat_map(|categories| ical::split_text_list(&categories.value))
+            .collect(),
+        configuration,
+    })
+}
+
+/// Finds the tags named like the categories, creating the missing ones.
+async fn category_tags(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    categories: &[String],
+) -> Result<Vec<i64>, sqlx::Error>
+{
+    let mut tags = Vec::new();
+
+    for category in categories
+    {
+        let tag = sqlx::query_scalar!(
+            "SELECT id FROM tags WHERE name = $1 ORDER BY id LIMIT 1",
+            category
+        )
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+        let tag = match tag
+        {
+            Some(tag) => tag,
+            None => sqlx::query_scalar!(
+                "INSERT INTO tags (name, color) VALUES ($1, $2) RETURNING id",
+                category,
+                IMPORTED_TAG_COLOR
+            )
+            .fetch_one(&mut **transaction)
+            .await?,
+        };
+
+        if !tags.contains(&tag)
+        {
+            tags.push(tag);
+        }
+    }
+
+    Ok(tags)
+}
+
+/// Stores the edited and cancelled occurrences of an imported recurrence.
+async fn import_overrides(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    event_id: i64,
+    time_zone: &Tz,
+    overrides: &[&Component],
+) -> Result<(), String>
+{
+    if overrides.is_empty()
+    {
+        return Ok(());
+    }
+
+    let recurrence_id = sqlx::query_scalar!("SELECT id FROM recurrences WHERE event_id = $1", event_id)
+        .fetch_optional(&mut **transaction)
+        .await
+        .map_err(error_message)?
+        .ok_or("RECURRENCE-ID on a non-recurring event.")?;
+
+    for vevent in overrides
+    {
+        let original_start_time = property_time(vevent, "RECURRENCE-ID", time_zone)?
+            .ok_or("Missing RECURRENCE-ID.")?;
+
+        let result = if is_cancelled(vevent)
+        {
+            sqlx::query!(
+                "UPDATE recurrences SET exdates = ARRAY_APPEND(exdates, $2) WHERE id = $1",
+                recurrence_id,
+                original_start_time
+            )
+            .execute(&mut **transaction)
+            .await
+        }
+        else
+        {
+            let (start_time, end_time) = vevent_times(vevent, time_zone)?;
+
+            if end_time <= start_time
+            {
+                return Err(format!("Occurrence of {} must end after it starts.", ical::format_utc(original_start_time)));
+            }
+
+            sqlx::query!(
+                "INSERT INTO schedules (recurrence_id, event_id, start_time, end_time, original_start_time)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (recurrence_id, original_start_time)
+                DO UPDATE SET start_time = EXCLUDED.start_time, end_time = EXCLUDED.end_time",
+                recurrence_id,
+                event_id,
+                start_time,
+                end_time,
+                original_start_time
+            )
+            .execute(&mut **transaction)
+            .await
+        };
+
+        result.map_err(error_message)?;
+    }
+
+    Ok(())
+}
+
+/// Creates or updates the event of a UID, with the VEVENTs sharing it: the
+/// series itself and its edited occurrences. An event previously imported by
+/// the same owner is replaced, keeping its participants.
+async fn import_event(
+    db_pool: &PgPool,
+    user_id: i64,
+    uid: &str,
+    vevents: &[&Component],
+) -> Result<(ImportStatus, i64), String>
+{
+    let (series, overrides): (Vec<&Component>, Vec<&Component>) = vevents.iter()
+        .partition(|vevent| vevent.property("RECURRENCE-ID").is_none());
+
+    let series = series.first().ok_or("Edited occurrences without their recurring event.")?;
+    let imported = parse_vevent(series)?;
+
+    let mut transaction = db_pool.begin().await.map_err(error_message)?;
+
+    let existing = sqlx::query_scalar!(
+        "SELECT events.id FROM events
+        JOIN users_events ON users_events.event_id = events.id
+        WHERE events.external_id = $1 AND users_events.user_id = $2 AND users_events.owner
+        ORDER BY events.id LIMIT 1",
+        uid,
+        user_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(error_message)?;
+
+    let tags = category_tags(&mut transaction, &imported.categories)
+        .await
+        .map_err(error_message)?;
+
+    let (status, event_id) = match existing
+    {
+        Some(event_id) =>
+        {
+            sqlx::query!(
+                "UPDATE events SET name = $1, description = $2, private = $3, time_zone = $4 WHERE id = $5",
+                imported.name,
+                imported.description,
+                imported.private,
+                imported.time_zone.name(),
+                event_id
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(error_message)?;
+
+            for query in [
+                "DELETE FROM events_tags WHERE event_id = $1",
+                "DELETE FROM schedules WHERE event_id = $1",
+                "DELETE FROM recurrences WHERE event_id = $1",
+            ]
+            {
+                sqlx::query(query)
+                    .bind(event_id)
+                    .execute(&mut *transaction)
+                    .await
+                    .map_err(error_message)?;
+            }
+
+            event::add_event_tags(&mut transaction, event_id, &tags)
+                .await
+                .map_err(error_message)?;
+
+            event::add_event_configuration(&mut transaction, event_id, imported.configuration, &imported.time_zone)
+                .await
+                .map_err(error_message)?;
+
+            (ImportStatus::Updated, event_id)
+        },
+        None =>
+        {
+            let event_id = event::insert_event(&mut transaction, user_id, CreateEventRequest {
+                name          : imported.name,
+                description   : imported.description,
+                private       : Some(imported.private),
+                super_event_id: None,
+                tags          : Some(tags),
+                time_zone     : Some(imported.time_zone),
+                configuration : Some(imported.configuration),
+                capacity      : None,
+            })
+            .await
+            .map_err(error_message)?;
+
+            sqlx::query!("UPDATE events SET external_id = $1 WHERE id = $2", uid, event_id)
+                .execute(&mut *transaction)
+                .await
+                .map_err(error_message)?;
+
+            (ImportStatus::Created, event_id)
+        },
+    };
+
+    import_overrides(&mut transaction, event_id, &imported.time_zone, &overrides).await?;
+
+    transaction.commit().await.map_err(error_message)?;
+
+    Ok((status, event_id))
+}
+
+/// Imports the VEVENTs of an iCalendar body for the current user, each UID in
+/// its own transaction, reporting what happened to every one of them.
+pub async fn import_calendar(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    body: String,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    let calendars = ical::parse(&body).map_err(|e| ApiError::BadRequest(format!("Invalid iCalendar: {e}")))?;
+
+    let mut items = Vec::new();
+    let mut vevents: Vec<(Option<&str>, Vec<&Component>)> = Vec::new();
+
+    for component in calendars.iter()
+        .filter(|calendar| calendar.name == "VCALENDAR")
+        .flat_map(|calendar| calendar.components.iter())
+    {
+        let uid = component.property("UID").map(|uid| uid.value.as_str());
+
+        match component.name.as_str()
+        {
+            "VEVENT" => match vevents.iter_mut().find(|(other, _)| uid.is_some() && *other == uid)
+            {
+                Some((_, group)) => group.push(component),
+                None => vevents.push((uid, vec![component])),
+            },
+            "VTIMEZONE" => {},
+            name => items.push(ImportItem::new(
+                uid,
+                ImportStatus::Skipped,
+                None,
+                Some(format!("Unsupported {name} component.")),
+            )),
+        }
+    }
+
+    for (uid, group) in vevents
+    {
+        let item = match uid
+        {
+            None => ImportItem::new(None, ImportStatus::Failed, None, Some("Missing UID.".to_owned())),
+            Some(uid) if group.iter().all(|vevent| is_cancelled(vevent)) => ImportItem::new(
+                Some(uid),
+                ImportStatus::Skipped,
+                None,
+                Some("Cancelled event.".to_owned()),
+            ),
+            Some(uid) => match import_event(&db_pool, user.id, uid, &group).await
+            {
+                Ok((status, event_id)) => ImportItem::new(Some(uid), status, Some(event_id), None),
+                Err(message) => ImportItem::new(Some(uid), ImportStatus::Failed, None, Some(message)),
+            },
+        };
+
+        items.push(item);
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Calendar imported.",
+            "items"  : items,
+        })),
+    ))
+}
@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+use axum::{extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use serde_json::{json, Value};
+use chrono::{DateTime, Utc};
+use validator::Validate;
+use crate::{
+    handlers::{attachment, mention, reaction::{self, Reaction}, user::User},
+    helpers::{
+        auth::CurrentUser,
+        error::ApiError,
+        extract::{Json, Path, ValidJson, ValidQuery},
+        policy::{self, Action},
+    },
+    notifications::Channels,
+    storage::SharedStorage,
+};
+
+const DEFAULT_THREADS_PER_PAGE: i64 = 20;
+
+#[derive(Deserialize, Validate)]
+pub struct AddCommentToEventRequest
+{
+    #[validate(length(min = 1, max = 255, message = "must have between 1 and 255 characters"))]
+    pub title            : String,
+    #[validate(length(min = 1, message = "must not be empty"))]
+    pub content          : String,
+    /// The comment of the same event this one replies to.
+    pub parent_comment_id: Option<i64>,
+}
+
+/// Notifies the users mentioned in the content, like `@42` or `@external_id`.
+pub async fn add_comment_to_event(
+    State(db_pool): State<PgPool>,
+    State(channels): State<Channels>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+    ValidJson(req): ValidJson<AddCommentToEventRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    policy::authorize_event(&db_pool, user.id, id, Action::Comment).await?;
+
+    if let Some(parent_comment_id) = req.parent_comment_id
+    {
+        let parent_event_id = sqlx::query_scalar!(
+            "SELECT event_id FROM events_comments WHERE id = $1 AND deleted_at IS NULL",
+            parent_comment_id
+        )
+        .fetch_optional(&db_pool)
+        .await?;
+
+        if parent_event_id != Some(id)
+        {
+            return Err(ApiError::Unprocessable("Parent comment not found.".into()));
+        }
+    }
+
+    let mut transaction = db_pool.begin().await?;
+
+    let comment = sqlx::query!(
+        "INSERT INTO events_comments (event_id, user_id, title, content, parent_comment_id)
+        VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        id,
+        user.id,
+        req.title,
+        req.content,
+        req.parent_comment_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Event not found.".into()))?;
+
+    let mentioned = mention::save_mentions(&mut transaction, comment.id, id, user.id, &req.content).await?;
+
+    transaction.commit().await?;
+
+    mention::notify_mentioned(&db_pool, &channels, id, &user.name, &req.title, &req.content, &mentioned).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Comment added to event successfully.",
+            "id": comment.id
+        })),
+    ))
+}
+
+/// How threads are rendered: replies inside the comments they answer, or
+/// every comment in reading order with its depth.
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThreadView
+{
+    #[default]
+    Nested,
+    Flat,
+}
+
+/// Pages go through threads, each with all of its replies.
+#[derive(Deserialize, Validate)]
+pub struct CommentsQuery
+{
+    #[validate(range(min = 1, message = "must be at least 1"))]
+    pub page    : Option<i64>,
+    #[validate(range(min = 1, max = 100, message = "must be between 1 and 100"))]
+    pub per_page: Option<i64>,
+    #[serde(default)]
+    pub view    : ThreadView,
+}
+
+#[derive(FromRow)]
+struct CommentRow
+{
+    id                : i64,
+    parent_comment_id : Option<i64>,
+    title             : String,
+    content           : String,
+    created_at        : DateTime<Utc>,
+    edited_at         : Option<DateTime<Utc>>,
+    deleted_at        : Option<DateTime<Utc>>,
+    author_id         : i64,
+    author_external_id: Option<String>,
+    author_name       : String,
+}
+
+/// Deleted comments keep their place in threads, without author nor text.
+#[derive(Debug, Serialize)]
+pub struct Comment
+{
+    pub id               : i64,
+    pub parent_comment_id: Option<i64>,
+    pub author           : Option<User>,
+    pub title            : Option<String>,
+    pub content          : Option<String>,
+    pub created_at       : DateTime<Utc>,
+    pub edited_at        : Option<DateTime<Utc>>,
+    pub deleted_at       : Option<DateTime<Utc>>,
+    pub reactions        : Vec<Reaction>,
+    /// Only in flat threads, 0 for the comments that start them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depth            : Option<usize>,
+    /// Only in nested threads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replies          : Option<Vec<Comment>>,
+}
+
+impl From<CommentRow> for Comment
+{
+    fn from(row: CommentRow) -> Self
+    {
+        let visible = row.deleted_at.is_none();
+        let author  = User { id: row.author_id, external_id: row.author_external_id, name: row.author_name };
+
+        Comment {
+            id               : row.id,
+            parent_comment_id: row.parent_comment_id,
+            author           : visible.then_some(author),
+            title            : visible.then_some(row.title),
+            content          : visible.then_some(row.content),
+            created_at       : row.created_at,
+            edited_at        : row.edited_at,
+            deleted_at       : row.deleted_at,
+            reactions        : Vec::new(),
+            depth            : None,
+            replies          : None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommentPage
+{
+    pub page    : i64,
+    pub per_page: i64,
+    /// Threads of the event, across every page.
+    pub threads : i64,
+    pub comments: Vec<Comment>,
+}
+
+/// Replies of a comment, oldest first, with theirs inside.
+fn nest(replies: &mut HashMap<Option<i64>, Vec<Comment>>, parent_id: Option<i64>) -> Vec<Comment>
+{
+    replies.remove(&parent_id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|mut comment| {
+            comment.replies = Some(nest(replies, Some(comment.id)));
+            comment
+        })
+        .collect()
+}
+
+/// Replies of a comment, each followed by theirs.
+fn flatten(
+    replies: &mut HashMap<Option<i64>, Vec<Comment>>,
+    parent_id: Option<i64>,
+    depth: usize,
+    comments: &mut Vec<Comment>,
+)
+{
+    for mut comment in replies.remove(&parent_id).unwrap_or_default()
+    {
+        let id = comment.id;
+
+        comment.depth = Some(depth);
+        comments.push(comment);
+
+        flatten(replies, Some(id), depth + 1, comments);
+    }
+}
+
+/// Threads oldest first, as visible as the event, with the reactions to each
+/// comment.
+pub async fn list_event_comments(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+    ValidQuery(query): ValidQuery<CommentsQuery>,
+) -> Result<(StatusCode, Json<CommentPage>), ApiError>
+{
+    policy::authorize_event(&db_pool, user.id, id, Action::View).await?;
+
+    let page     = query.page.unwrap_or(1);
+    let per_page = query.per_page.unwrap_or(DEFAULT_THREADS_PER_PAGE);
+
+    let threads = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM events_comments WHERE event_id = $1 AND parent_comment_id IS NULL"#,
+        id
+    )
+    .fetch_one(&db_pool)
+    .await?;
+
+    let rows = sqlx::query_as::<_, CommentRow>
+        ("WITH RECURSIVE thread AS (
+            (SELECT * FROM events_comments
+            WHERE event_id = $1 AND parent_comment_id IS NULL
+            ORDER BY created_at, id
+            LIMIT $2 OFFSET $3)
+            UNION ALL
+            SELECT events_comments.* FROM events_comments
+            JOIN thread ON events_comments.parent_comment_id = thread.id
+        )
+        SELECT thread.id, thread.parent_comment_id, thread.title, thread.content, thread.created_at,
+            thread.edited_at, thread.deleted_at,
+            users.id AS author_id, users.external_id AS author_external_id, users.name AS author_name
+        FROM thread
+        JOIN users ON users.id = thread.user_id
+        ORDER BY thread.created_at, thread.id")
+        .bind(id)
+        .bind(per_page)
+        .bind((page - 1) * per_page)
+        .fetch_all(&db_pool)
+        .await?;
+
+    let ids: Vec<i64> = rows.iter().map(|row| row.id).collect();
+
+    let mut reactions = reaction::list_reactions(&db_pool, &ids, user.id).await?;
+
+    let mut replies: HashMap<Option<i64>, Vec<Comment>> = HashMap::new();
+
+    for row in rows
+    {
+        let mut comment = Comment::from(row);
+
+        comment.reactions = reactions.remove(&comment.id).unwrap_or_default();
+        replies.entry(comment.parent_comment_id).or_default().push(comment);
+    }
+
+    let comments = match query.view
+    {
+        ThreadView::Nested => nest(&mut replies, None),
+        ThreadView::Flat   =>
+        {
+            let mut comments = Vec::new();
+            flatten(&mut replies, None, 0, &mut comments);
+            comments
+        },
+    };
+
+    Ok((
+        StatusCode::OK,
+        Json(CommentPage { page, per_page, threads, comments }),
+    ))
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateCommentRequest
+{
+    #[validate(length(min = 1, max = 255, message = "must have between 1 and 255 characters"))]
+    pub title  : String,
+    #[validate(length(min = 1, message = "must not be empty"))]
+    pub content: String,
+}
+
+/// Keeps the replaced version in the history of the comment, and notifies the
+/// users it mentions for the first time.
+pub async fn update_comment(
+    State(db_pool): State<PgPool>,
+    State(channels): State<Channels>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+    ValidJson(req): ValidJson<UpdateCommentRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    let event_id = policy::authorize_comment(&db_pool, user.id, id, Action::EditComment).await?;
+
+    let mut transaction = db_pool.begin().await?;
+
+    let revision = sqlx::query!(
+        "INSERT INTO events_comments_revisions (comment_id, title, content, created_at)
+        SELECT id, title, content, COALESCE(edited_at, created_at) FROM events_comments
+        WHERE id = $1 AND deleted_at IS NULL
+        FOR UPDATE",
+        id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    if revision.rows_affected() == 0
+    {
+        return Err(ApiError::NotFound("Comment not found.".into()));
+    }
+
+    sqlx::query!(
+        "UPDATE events_comments SET title = $1, content = $2, edited_at = NOW()
+        WHERE id = $3",
+        req.title,
+        req.content,
+        id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    let mentioned = mention::save_mentions(&mut transaction, id, event_id, user.id, &req.content).await?;
+
+    transaction.commit().await?;
+
+    mention::notify_mentioned(&db_pool, &channels, event_id, &user.name, &req.title, &req.content, &mentioned).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"message": "Comment updated successfully."})),
+    ))
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct Revision
+{
+    pub title     : String,
+    pub content   : String,
+    /// When this version was written.
+    pub created_at: DateTime<Utc>,
+}
+
+/// Every version of a comment, oldest first, ending with the current one.
+pub async fn get_comment_history(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> Result<(StatusCode, Json<Vec<Revision>>), ApiError>
+{
+    policy::authorize_comment(&db_pool, user.id, id, Action::View).await?;
+
+    let revisions = sqlx::query_as::<_, Revision>
+        ("SELECT title, content, created_at FROM (
+            SELECT id, title, content, created_at FROM events_comments_revisions WHERE comment_id = $1
+            UNION ALL
+            SELECT NULL, title, content, COALESCE(edited_at, created_at) FROM events_comments WHERE id = $1
+        ) AS revisions
+        ORDER BY id NULLS LAST")
+        .bind(id)
+        .fetch_all(&db_pool)
+        .await?;
+
+    Ok((StatusCode::OK, Json(revisions)))
+}
+
+/// Leaves a tombstone in place of the comment, so its replies stay in the
+/// thread, and forgets its text, history, reactions and files.
+pub async fn delete_comment(
+    State(db_pool): State<PgPool>,
+    State(storage): State<SharedStorage>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    policy::authorize_comment(&db_pool, user.id, id, Action::DeleteComment).await?;
+
+    let mut transaction = db_pool.begin().await?;
+
+    sqlx::query!("DELETE FROM events_comments_revisions WHERE comment_id = $1", id)
+        .execute(&mut *transaction)
+        .await?;
+
+    sqlx::query!("DELETE FROM events_comments_reactions WHERE comment_id = $1", id)
+        .execute(&mut *transaction)
+        .await?;
+
+    let attachment_ids = sqlx::query_scalar!("DELETE FROM attachments WHERE comment_id = $1 RETURNING id", id)
+        .fetch_all(&mut *transaction)
+        .await?;
+
+    let result = sqlx::query!
+        ("UPDATE events_comments SET title = '', content = '', deleted_at = NOW()
+        WHERE id = $1 AND deleted_at IS NULL", id)
+        .execute(&mut *transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    attachment::delete_files(&storage, &attachment_ids).await;
+
+    match result.rows_affected()
+    {
+        0 => Err(ApiError::NotFound("Comment not found.".into())),
+        _ => Ok((
+            StatusCode::OK,
+            Json(json!({"message": "Comment deleted successfully."})),
+        )),
+    }
+}
@@ -1,10 +1,17 @@
-use axum::{extract::{Path, State}, http::StatusCode, Json};
+use axum::{extract::State, http::StatusCode};
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, PgPool};
 use serde_json::{json, Value};
 use sqlx::Type;
+use validator::{Validate, ValidateEmail, ValidationErrors};
+use crate::helpers::{
+    auth::CurrentUser,
+    error::ApiError,
+    extract::{Json, Path, ValidJson},
+    validation,
+};
 
-#[derive(Debug, Serialize, Deserialize, Type)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type)]
 #[serde(rename_all = "lowercase")]
 #[sqlx(type_name = "contact_type", rename_all = "lowercase")]
 pub enum ContactType
@@ -19,37 +26,67 @@ pub struct UserContact
     pub id          : i64,
     pub user_id     : i64,
     pub contact     : String,
-    pub contact_type: String,
+    #[sqlx(rename = "type")]
+    pub r#type      : ContactType,
+    /// Gets the notifications that go to a single contact.
+    pub preferred   : bool,
 }
 
 #[derive(Deserialize)]
 pub struct CreateContactRequest
 {
-    pub user_id: i64,
-    pub contact: String,
-    pub r#type : ContactType,
+    pub contact  : String,
+    pub r#type   : ContactType,
+    #[serde(default)]
+    pub preferred: bool,
+}
+
+/// The contact is checked according to its type.
+impl Validate for CreateContactRequest
+{
+    fn validate(&self) -> Result<(), ValidationErrors>
+    {
+        let error = match self.r#type
+        {
+            ContactType::Email if !self.contact.validate_email() =>
+                validation::error("email", "must be an email address"),
+            ContactType::Phone if !validation::is_phone(&self.contact) =>
+                validation::error("phone", "must be a phone number with 7 to 15 digits, like +55 11 91234-5678"),
+            _ if self.contact.chars().count() > 255 =>
+                validation::error("length", "must have at most 255 characters"),
+            _ => return Ok(()),
+        };
+
+        Err(validation::field_error("contact", error))
+    }
 }
 
 pub async fn create_contact(
     State(db_pool): State<PgPool>,
-    Json(req): Json<CreateContactRequest>,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+    CurrentUser(user): CurrentUser,
+    ValidJson(req): ValidJson<CreateContactRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
 {
+    let mut transaction = db_pool.begin().await?;
+
+    if req.preferred
+    {
+        sqlx::query!("UPDATE users_contacts SET preferred = false WHERE user_id = $1", user.id)
+            .execute(&mut *transaction)
+            .await?;
+    }
+
     let result = sqlx::query!(
-        "INSERT INTO users_contacts (user_id, contact, type) VALUES ($1, $2, $3) RETURNING id",
-        req.user_id,
+        "INSERT INTO users_contacts (user_id, contact, type, preferred) VALUES ($1, $2, $3, $4) RETURNING id",
+        user.id,
         req.contact,
-        req.r#type as ContactType
+        req.r#type as ContactType,
+        req.preferred
     )
-    .fetch_one(&db_pool)
-    .await
-    .map_err(|e| (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        Json(json!({
-            "status": "error",
-            "message": e.to_string()
-        })),
-    ))?;
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
 
     Ok((
         StatusCode::CREATED,
@@ -60,29 +97,53 @@ pub async fn create_contact(
     ))
 }
 
+/// Makes a contact the preferred one of the current user, instead of any other.
+pub async fn prefer_contact(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    let mut transaction = db_pool.begin().await?;
+
+    sqlx::query!("UPDATE users_contacts SET preferred = false WHERE user_id = $1 AND preferred", user.id)
+        .execute(&mut *transaction)
+        .await?;
+
+    let result = sqlx::query!(
+        "UPDATE users_contacts SET preferred = true WHERE id = $1 AND user_id = $2",
+        id,
+        user.id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    if result.rows_affected() == 0
+    {
+        return Err(ApiError::NotFound("Contact not found.".into()));
+    }
+
+    transaction.commit().await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"message": "Contact preferred successfully."})),
+    ))
+}
 
 pub async fn delete_contact(
     State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
     Path(id): Path<i64>,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+) -> Result<(StatusCode, Json<Value>), ApiError>
 {
-    let result = sqlx::query!("DELETE FROM users_contacts WHERE id = $1", id)
+    let result = sqlx::query!("DELETE FROM users_contacts WHERE id = $1 AND user_id = $2", id, user.id)
         .execute(&db_pool)
-        .await
-        .map_err(|e| (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({
-                "status": "error",
-                "message": e.to_string()
-            })),
-        ))?;
+        .await?;
 
     match result.rows_affected()
     {
-        0 => Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"message": "Contact not found."})),
-        )),
+        0 => Err(ApiError::NotFound("Contact not found.".into())),
         _ => Ok((
             StatusCode::OK,
             Json(json!({"message": "Contact deleted successfully.",})),
@@ -92,21 +153,14 @@ pub async fn delete_contact(
 
 pub async fn list_contacts(
     State(db_pool): State<PgPool>,
-    Path(user_id): Path<i64>,
-) -> Result<(StatusCode, Json<Vec<UserContact>>), (StatusCode, Json<Value>)>
+    CurrentUser(user): CurrentUser,
+) -> Result<(StatusCode, Json<Vec<UserContact>>), ApiError>
 {
     let contacts = sqlx::query_as::<_, UserContact>
         ("SELECT * FROM users_contacts WHERE user_id = $1")
-        .bind(user_id)
+        .bind(user.id)
         .fetch_all(&db_pool)
-        .await
-        .map_err(|e| (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({
-                "status": "error",
-                "message": e.to_string()
-            })),
-        ))?;
+        .await?;
 
     Ok((StatusCode::OK, Json(contacts)))
 }
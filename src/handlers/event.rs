@@ -1,569 +1,1482 @@
-use axum::{extract::{Path, State}, http::StatusCode, Json,};
+use std::cmp::Ordering;
+use axum::{extract::State, http::StatusCode};
 use serde::{Deserialize, Serialize};
-use sqlx::{query_builder::QueryBuilder, FromRow, PgPool, Type};
+use sqlx::{query_builder::QueryBuilder, FromRow, PgPool};
 use serde_json::{json, Value};
-use chrono::{Datelike, Duration, NaiveDateTime};
-use chronoutil::{shift_months, shift_years};
-use crate::helpers::error::database_err_mapper;
-
-#[derive(Debug, Deserialize, sqlx::Type, Eq, PartialEq)]
-#[serde(rename_all = "lowercase")]
-#[sqlx(type_name = "week_day", rename_all = "lowercase")]
-pub enum Weekday
-{
-    Monday,
-    Tuesday,
-    Wednesday,
-    Thursday,
-    Friday,
-    Saturday,
-    Sunday,
-}
-impl From<chrono::Weekday> for Weekday
-{
-    fn from(weekday: chrono::Weekday) -> Self
-    {
-        match weekday {
-            chrono::Weekday::Mon => Weekday::Monday,
-            chrono::Weekday::Tue => Weekday::Tuesday,
-            chrono::Weekday::Wed => Weekday::Wednesday,
-            chrono::Weekday::Thu => Weekday::Thursday,
-            chrono::Weekday::Fri => Weekday::Friday,
-            chrono::Weekday::Sat => Weekday::Saturday,
-            chrono::Weekday::Sun => Weekday::Sunday,
-        }
-    }
-}
-
-
-
-#[derive(Debug, sqlx::Type)]
-#[sqlx(type_name = "recurrence_type", rename_all = "lowercase")]
-enum RecurrenceType
-{
-    Daily,
-    Weekly,
-    Monthly,
-    Yearly,
-}
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use validator::{Validate, ValidationErrors};
+use crate::{
+    handlers::{
+        attachment,
+        participation::{Attendance, ParticipationStatus, Responses},
+        tag::Tag,
+        waitlist::{self, Places},
+    },
+    helpers::{
+        auth::CurrentUser,
+        conflict::{self, ConflictMode},
+        error::ApiError,
+        extract::{Json, Path, Query, ValidJson},
+        policy::{self, Action},
+        recurrence::{self, Recurrence, RecurrenceType, Schedule, Weekday},
+        time_zone::DateTimeInput,
+        validation,
+    },
+    notifications::Channels,
+    storage::SharedStorage,
+};
 
 #[derive(Deserialize)]
 #[serde(rename_all = "lowercase", tag = "type")]
 pub enum EventConfiguration
 {
     Daily {
-        start_time: NaiveDateTime,
-        end_time: NaiveDateTime,
+        start_time: DateTimeInput,
+        end_time: DateTimeInput,
         step: Option<i16>,
         repetitions: Option<i16>,
-        end_date: Option<NaiveDateTime>,
+        end_date: Option<DateTimeInput>,
     },
     Weekly {
-        start_time: NaiveDateTime,
-        end_time: NaiveDateTime,
+        start_time: DateTimeInput,
+        end_time: DateTimeInput,
         step: Option<i16>,
         repetitions: Option<i32>,
-        end_date: Option<NaiveDateTime>,
+        end_date: Option<DateTimeInput>,
         days_of_week: Vec<Weekday>,
     },
     Monthly {
-        start_time: NaiveDateTime,
-        end_time: NaiveDateTime,
+        start_time: DateTimeInput,
+        end_time: DateTimeInput,
         step: Option<i16>,
         repetitions: Option<i16>,
-        end_date: Option<NaiveDateTime>,
+        end_date: Option<DateTimeInput>,
     },
     Yearly {
-        start_time: NaiveDateTime,
-        end_time: NaiveDateTime,
+        start_time: DateTimeInput,
+        end_time: DateTimeInput,
         step: Option<i16>,
         repetitions: Option<i16>,
-        end_date: Option<NaiveDateTime>,
+        end_date: Option<DateTimeInput>,
     },
     Individual {
-        start_time: NaiveDateTime,
-        end_time: NaiveDateTime,
+        start_time: DateTimeInput,
+        end_time: DateTimeInput,
+    },
+    Rule {
+        rrule: String,
+        dtstart: DateTimeInput,
+        duration: i32,
+        exdates: Option<Vec<DateTimeInput>>,
+    },
+}
+
+/// Checks the parts of a recurring configuration besides its times.
+fn validate_recurrence(
+    errors: &mut ValidationErrors,
+    start_time: &DateTimeInput,
+    step: Option<i16>,
+    repetitions: Option<i32>,
+    end_date: Option<&DateTimeInput>,
+)
+{
+    if step.is_some_and(|step| step < 1)
+    {
+        errors.add("step", validation::error("range", "must be at least 1"));
+    }
+
+    if repetitions.is_some_and(|repetitions| !(1..=i32::from(i16::MAX)).contains(&repetitions))
+    {
+        errors.add("repetitions", validation::error("range", "must be between 1 and 32767"));
+    }
+
+    if end_date.and_then(|end_date| end_date.compare(start_time)).is_some_and(Ordering::is_lt)
+    {
+        errors.add("end_date", validation::error("time_range", "must not be before start_time"));
     }
 }
 
-#[derive(Deserialize)]
+impl Validate for EventConfiguration
+{
+    fn validate(&self) -> Result<(), ValidationErrors>
+    {
+        let mut errors = ValidationErrors::new();
+
+        match self
+        {
+            EventConfiguration::Individual { start_time, end_time } =>
+                validation::time_range(&mut errors, start_time, end_time),
+            EventConfiguration::Daily { start_time, end_time, step, repetitions, end_date }
+            | EventConfiguration::Monthly { start_time, end_time, step, repetitions, end_date }
+            | EventConfiguration::Yearly { start_time, end_time, step, repetitions, end_date } =>
+            {
+                validation::time_range(&mut errors, start_time, end_time);
+                validate_recurrence(&mut errors, start_time, *step, repetitions.map(i32::from), end_date.as_ref());
+            },
+            EventConfiguration::Weekly { start_time, end_time, step, repetitions, end_date, days_of_week } =>
+            {
+                validation::time_range(&mut errors, start_time, end_time);
+                validate_recurrence(&mut errors, start_time, *step, *repetitions, end_date.as_ref());
+
+                if days_of_week.is_empty()
+                {
+                    errors.add("days_of_week", validation::error("length", "must have at least one day"));
+                }
+            },
+            EventConfiguration::Rule { rrule, duration, .. } =>
+            {
+                if rrule.trim().is_empty()
+                {
+                    errors.add("rrule", validation::error("length", "must not be empty"));
+                }
+
+                if *duration < 1
+                {
+                    errors.add("duration", validation::error("range", "must be at least 1 minute"));
+                }
+            },
+        }
+
+        match errors.is_empty()
+        {
+            true  => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+#[derive(Deserialize, Validate)]
 pub struct CreateEventRequest
 {
+    #[validate(length(min = 1, max = 255, message = "must have between 1 and 255 characters"))]
     pub name          : String,
+    #[validate(length(max = 255, message = "must have at most 255 characters"))]
     pub description   : String,
     pub private       : Option<bool>,
-    pub user_id       : i64,
     pub super_event_id: Option<i64>,
+    #[validate(custom(function = "validation::unique_ids"))]
     pub tags          : Option<Vec<i64>>,
+    pub time_zone     : Option<Tz>,
+    #[validate(nested)]
     pub configuration : Option<EventConfiguration>,
+    /// Participants that didn't decline, the rest wait for a place.
+    #[validate(range(min = 1, message = "must be at least 1"))]
+    pub capacity      : Option<i32>,
 }
 
-async fn create_recurrence(
-    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
-    recurrence_type: RecurrenceType,
-    step: i16,
-    repetitions: i16,
-    end_date: NaiveDateTime,
-) -> Result<i64, sqlx::Error>
+struct RecurrenceRule
 {
-    let recurrence_result = sqlx::query!(
-        "INSERT INTO recurrences (type, step, repetitions, end_date)
-        VALUES ($1, $2, $3, $4) RETURNING id",
-        recurrence_type as RecurrenceType,
-        step,
-        repetitions,
-        end_date
-    )
-    .fetch_one(&mut **transaction)
-    .await?;
+    recurrence_type: RecurrenceType,
+    step           : i16,
+    repetitions    : Option<i16>,
+    end_date       : Option<DateTime<Utc>>,
+    days_of_week   : Vec<Weekday>,
+    rrule          : Option<String>,
+    start_time     : DateTime<Utc>,
+    end_time       : DateTime<Utc>,
+    exdates        : Vec<DateTime<Utc>>,
+}
 
-    Ok(recurrence_result.id)
+enum ConfiguredSchedule
+{
+    Individual(DateTime<Utc>, DateTime<Utc>),
+    Recurring(RecurrenceRule),
 }
 
-pub async fn create_event(
-    State(db_pool): State<PgPool>,
-    Json(req): Json<CreateEventRequest>,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+/// The last check of a schedule, for the times only comparable once resolved.
+fn check_time_range(start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> Result<(), ApiError>
 {
-    let mut transaction = db_pool.begin().await.map_err(database_err_mapper)?;
+    match end_time > start_time
+    {
+        true  => Ok(()),
+        false => Err(ApiError::Validation(validation::field_error(
+            "end_time",
+            validation::error("time_range", "must be after start_time"),
+        ))),
+    }
+}
 
-    let event_result = sqlx::query!(
-        "INSERT INTO events (name, description, private, super_event_id)
-        VALUES ($1, $2, $3, $4) RETURNING id",
-        req.name,
-        req.description,
-        req.private.unwrap_or(false),
-        req.super_event_id
-    )
-    .fetch_one(&mut *transaction)
-    .await
-    .map_err(database_err_mapper)?;
+pub fn resolve_time(
+    date_time: DateTimeInput,
+    time_zone: &Tz,
+) -> Result<DateTime<Utc>, ApiError>
+{
+    date_time.resolve(time_zone)
+        .ok_or_else(|| ApiError::BadRequest(format!("Time skipped by a DST transition in {time_zone}.")))
+}
 
-    sqlx::query!(
-        "INSERT INTO users_events (user_id, event_id, confirmation, owner)
-        VALUES ($1, $2, true, true)",
-        req.user_id,
-        event_result.id
-    )
-    .execute(&mut *transaction)
-    .await
-    .map_err(database_err_mapper)?;
+/// Steps and repetitions are stored as `SMALLINT`, larger ones are rejected
+/// rather than wrapped.
+fn small_count<T: TryInto<i16>>(field: &'static str, message: &'static str, value: T) -> Result<i16, ApiError>
+{
+    value.try_into().map_err(|_| ApiError::Validation(validation::field_error(
+        field,
+        validation::error("range", message),
+    )))
+}
 
-    if let Some(tags) = req.tags
+/// Turns a configuration into either a single schedule or the rule that generates
+/// its occurrences, reading wall-clock times in the event time zone. Without
+/// repetitions nor an end date, a recurrence never ends.
+fn parse_configuration(
+    configuration: EventConfiguration,
+    time_zone: &Tz,
+) -> Result<ConfiguredSchedule, ApiError>
+{
+    let (recurrence_type, start_time, end_time, step, repetitions, end_date, days_of_week) = match configuration
     {
-        let mut insert_event_tags_query = QueryBuilder::new(
-            "INSERT INTO events_tags (event_id, tag_id)");
-    
-        insert_event_tags_query.push_values(tags.iter(), |mut b, tag_id|
+        EventConfiguration::Individual { start_time, end_time } =>
         {
-            b.push_bind(event_result.id)
-            .push_bind(tag_id);
-        });
-
-        insert_event_tags_query.build()
-            .execute(&mut *transaction)
-            .await
-            .map_err(database_err_mapper)?;
-    }
+            let start_time = resolve_time(start_time, time_zone)?;
+            let end_time   = resolve_time(end_time, time_zone)?;
 
-    let mut schedule_times: Vec<(NaiveDateTime, NaiveDateTime)> = Vec::new();
+            check_time_range(start_time, end_time)?;
 
-    let recurrence_id = match req.configuration
-    {
-        Some(EventConfiguration::Daily { start_time, end_time, step, repetitions, end_date }) =>
+            return Ok(ConfiguredSchedule::Individual(start_time, end_time));
+        },
+        EventConfiguration::Rule { rrule, dtstart, duration, exdates } =>
         {
-            let final_step        = step.unwrap_or(1);
-            let final_repetitions = repetitions.unwrap_or(730);
-            let final_end_date    = end_date.unwrap_or(start_time + Duration::days(final_step as i64 * final_repetitions as i64));
+            let rrule   = rrule.trim().trim_start_matches("RRULE:").to_owned();
+            let dtstart = resolve_time(dtstart, time_zone)?;
 
-            let mut curr_start_time = start_time;
-            let mut curr_end_time   = end_time;
-
-            while curr_end_time <= final_end_date
+            if recurrence::is_sub_daily(&rrule)
             {
-                schedule_times.push((curr_start_time, curr_end_time));
-
-                curr_start_time += Duration::days(final_step as i64);
-                curr_end_time   += Duration::days(final_step as i64);
+                return Err(ApiError::Validation(validation::field_error(
+                    "rrule",
+                    validation::error("frequency", "must not repeat more than once a day"),
+                )));
             }
 
-            Some(create_recurrence(&mut transaction, RecurrenceType::Daily, final_step, final_repetitions, final_end_date)
-                .await
-                .map_err(database_err_mapper)?)
+            let rrule_set = recurrence::parse_rrule(&rrule, dtstart, *time_zone)
+                .map_err(|e| ApiError::BadRequest(format!("Invalid rrule: {e}")))?;
+
+            let step = rrule_set.get_rrule()
+                .first()
+                .map_or(Ok(1), |parsed_rrule| small_count("rrule", "must have an INTERVAL of at most 32767", parsed_rrule.get_interval()))?;
+
+            return Ok(ConfiguredSchedule::Recurring(RecurrenceRule {
+                recurrence_type: RecurrenceType::Rule,
+                step,
+                repetitions    : None,
+                end_date       : None,
+                days_of_week   : Vec::new(),
+                rrule          : Some(rrule),
+                start_time     : dtstart,
+                end_time       : dtstart + Duration::minutes(duration as i64),
+                exdates        : exdates.unwrap_or_default()
+                    .into_iter()
+                    .map(|exdate| resolve_time(exdate, time_zone))
+                    .collect::<Result<_, _>>()?,
+            }));
         },
-        Some(EventConfiguration::Weekly { start_time, end_time, step, repetitions, end_date, days_of_week }) =>
-        {
-            let final_step        = step.unwrap_or(1);
-            let final_repetitions = repetitions.unwrap_or(156) as i16;
-            let final_end_date    = end_date.unwrap_or(start_time + Duration::weeks(final_step as i64 * final_repetitions as i64));
+        EventConfiguration::Daily { start_time, end_time, step, repetitions, end_date } =>
+            (RecurrenceType::Daily, start_time, end_time, step, repetitions, end_date, Vec::new()),
+        EventConfiguration::Weekly { start_time, end_time, step, repetitions, end_date, days_of_week } =>
+            (
+                RecurrenceType::Weekly,
+                start_time,
+                end_time,
+                step,
+                repetitions.map(|repetitions| small_count("repetitions", "must be at most 32767", repetitions)).transpose()?,
+                end_date,
+                days_of_week,
+            ),
+        EventConfiguration::Monthly { start_time, end_time, step, repetitions, end_date } =>
+            (RecurrenceType::Monthly, start_time, end_time, step, repetitions, end_date, Vec::new()),
+        EventConfiguration::Yearly { start_time, end_time, step, repetitions, end_date } =>
+            (RecurrenceType::Yearly, start_time, end_time, step, repetitions, end_date, Vec::new()),
+    };
 
-            let mut curr_start_time = start_time;
-            let mut curr_end_time   = end_time;
+    let start_time = resolve_time(start_time, time_zone)?;
+    let end_time   = resolve_time(end_time, time_zone)?;
 
-            while curr_start_time <= final_end_date
-            {
-                if days_of_week.contains(&curr_end_time.weekday().into())
-                {
-                    schedule_times.push((curr_start_time, curr_end_time));
-                }
-                curr_start_time += Duration::days(1);
-                curr_end_time   += Duration::days(1);
-            }
-            
-            let recurrence_id = create_recurrence(&mut transaction, RecurrenceType::Weekly, final_step, final_repetitions, final_end_date)
-                .await
-                .map_err(database_err_mapper)?;
+    check_time_range(start_time, end_time)?;
+
+    Ok(ConfiguredSchedule::Recurring(RecurrenceRule {
+        recurrence_type,
+        step      : step.unwrap_or(1),
+        repetitions,
+        end_date  : end_date.map(|end_date| resolve_time(end_date, time_zone)).transpose()?,
+        days_of_week,
+        rrule     : None,
+        start_time,
+        end_time,
+        exdates   : Vec::new(),
+    }))
+}
 
-            let mut insert_recurrence_week_days_query = QueryBuilder::new(
-                "INSERT INTO recurrences_week_days (recurrence_id, week_day)");
+async fn create_recurrence_week_days(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    recurrence_id: i64,
+    days_of_week: &[Weekday],
+) -> Result<(), sqlx::Error>
+{
+    if days_of_week.is_empty()
+    {
+        return Ok(());
+    }
 
-            insert_recurrence_week_days_query.push_values(days_of_week.iter(), |mut b, day_of_week|
-            {
-                b.push_bind(recurrence_id)
-                .push_bind(day_of_week);
-            });
+    let mut insert_recurrence_week_days_query = QueryBuilder::new(
+        "INSERT INTO recurrences_week_days (recurrence_id, week_day)");
 
-            insert_recurrence_week_days_query.build()
-                .execute(&mut *transaction)
-                .await
-                .map_err(database_err_mapper)?;
+    insert_recurrence_week_days_query.push_values(days_of_week.iter(), |mut b, day_of_week|
+    {
+        b.push_bind(recurrence_id)
+        .push_bind(day_of_week);
+    });
 
-            Some(recurrence_id)
-        },
-        Some(EventConfiguration::Monthly { start_time, end_time, step, repetitions, end_date }) =>
-        {
-            let final_step        = step.unwrap_or(1);
-            let final_repetitions = repetitions.unwrap_or(60);
-            let final_end_date    = end_date.unwrap_or(shift_months(start_time, final_step as i32 * final_repetitions as i32));
+    insert_recurrence_week_days_query.build()
+        .execute(&mut **transaction)
+        .await?;
 
-            let mut curr_start_time = start_time;
-            let mut curr_end_time   = end_time;
+    Ok(())
+}
 
-            while curr_end_time <= final_end_date
-            {
-                schedule_times.push((curr_start_time, curr_end_time));
+async fn create_recurrence(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    event_id: i64,
+    rule: &RecurrenceRule,
+) -> Result<i64, sqlx::Error>
+{
+    let recurrence_result = sqlx::query!(
+        "INSERT INTO recurrences (event_id, type, step, repetitions, end_date, rrule, start_time, end_time, exdates)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id",
+        event_id,
+        rule.recurrence_type as RecurrenceType,
+        rule.step,
+        rule.repetitions,
+        rule.end_date,
+        rule.rrule,
+        rule.start_time,
+        rule.end_time,
+        &rule.exdates
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
 
-                curr_start_time = shift_months(curr_start_time, final_step as i32);
-                curr_end_time   = shift_months(curr_end_time, final_step as i32);
-            }
+    create_recurrence_week_days(transaction, recurrence_result.id, &rule.days_of_week).await?;
 
-            Some(create_recurrence(&mut transaction, RecurrenceType::Monthly, final_step, final_repetitions, final_end_date)
-                .await
-                .map_err(database_err_mapper)?)
-        },
-        Some(EventConfiguration::Yearly { start_time, end_time, step, repetitions, end_date }) =>
-        {
-            let final_step        = step.unwrap_or(1);
-            let final_repetitions = repetitions.unwrap_or(10);
-            let final_end_date    = end_date.unwrap_or(shift_years(start_time, final_step as i32 * final_repetitions as i32));
+    Ok(recurrence_result.id)
+}
 
-            let mut curr_start_time = start_time;
-            let mut curr_end_time   = end_time;
+pub async fn add_event_tags(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    event_id: i64,
+    tags: &[i64],
+) -> Result<(), sqlx::Error>
+{
+    if tags.is_empty()
+    {
+        return Ok(());
+    }
 
-            while curr_end_time <= final_end_date
-            {
-                schedule_times.push((curr_start_time, curr_end_time));
+    let mut insert_event_tags_query = QueryBuilder::new(
+        "INSERT INTO events_tags (event_id, tag_id)");
 
-                curr_start_time = shift_years(curr_start_time, final_step as i32);
-                curr_end_time   = shift_years(curr_end_time, final_step as i32);
-            }
+    insert_event_tags_query.push_values(tags.iter(), |mut b, tag_id|
+    {
+        b.push_bind(event_id)
+        .push_bind(tag_id);
+    });
+
+    insert_event_tags_query.build()
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
 
-            Some(create_recurrence(&mut transaction, RecurrenceType::Yearly, final_step, final_repetitions, final_end_date)
-                .await
-                .map_err(database_err_mapper)?)
+/// Stores the schedule or recurrence described by a configuration.
+pub async fn add_event_configuration(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    event_id: i64,
+    configuration: EventConfiguration,
+    time_zone: &Tz,
+) -> Result<(), ApiError>
+{
+    match parse_configuration(configuration, time_zone)?
+    {
+        ConfiguredSchedule::Individual(start_time, end_time) =>
+        {
+            sqlx::query!(
+                "INSERT INTO schedules (event_id, start_time, end_time)
+                VALUES ($1, $2, $3)",
+                event_id,
+                start_time,
+                end_time
+            )
+            .execute(&mut **transaction)
+            .await?;
         },
-        Some(EventConfiguration::Individual { start_time, end_time }) =>
+        ConfiguredSchedule::Recurring(rule) =>
         {
-            schedule_times.push((start_time, end_time));
-            None
+            create_recurrence(transaction, event_id, &rule)
+                .await?;
         },
-        None => None
-    };
+    }
+
+    Ok(())
+}
+
+/// Inserts an event owned by `owner_id` with its tags and configuration.
+pub async fn insert_event(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    owner_id: i64,
+    req: CreateEventRequest,
+) -> Result<i64, ApiError>
+{
+    let time_zone = req.time_zone.unwrap_or(Tz::UTC);
+
+    let event_result = sqlx::query!(
+        "INSERT INTO events (name, description, private, super_event_id, time_zone, capacity)
+        VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+        req.name,
+        req.description,
+        req.private.unwrap_or(false),
+        req.super_event_id,
+        time_zone.name(),
+        req.capacity
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO users_events (user_id, event_id, status, responded_at, owner)
+        VALUES ($1, $2, 'accepted', NOW(), true)",
+        owner_id,
+        event_result.id
+    )
+    .execute(&mut **transaction)
+    .await?;
 
-    if !schedule_times.is_empty()
+    if let Some(tags) = req.tags
     {
-        let mut insert_schedules_query = QueryBuilder::new(
-            "INSERT INTO schedules (event_id, recurrence_id, start_time, end_time)");
-    
-        insert_schedules_query.push_values(schedule_times.iter(), |mut b, (start_time, end_time)|
-        {
-            b.push_bind(event_result.id)
-            .push_bind(recurrence_id)
-            .push_bind(start_time)
-            .push_bind(end_time);
-        });
-        
-        insert_schedules_query.build()
-            .execute(&mut *transaction)
-            .await
-            .map_err(database_err_mapper)?;
+        add_event_tags(transaction, event_result.id, &tags)
+            .await?;
     }
 
-    transaction.commit().await.map_err(database_err_mapper)?;
+    if let Some(configuration) = req.configuration
+    {
+        add_event_configuration(transaction, event_result.id, configuration, &time_zone).await?;
+    }
+
+    Ok(event_result.id)
+}
+
+/// Warns about the schedules that overlap others of the owner, or rejects the
+/// event in strict mode.
+pub async fn create_event(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Query(mode): Query<ConflictMode>,
+    ValidJson(req): ValidJson<CreateEventRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    if let Some(super_event_id) = req.super_event_id
+    {
+        policy::authorize_event(&db_pool, user.id, super_event_id, Action::Edit).await?;
+    }
+
+    let mut transaction = db_pool.begin().await?;
+
+    let id = insert_event(&mut transaction, user.id, req).await?;
+
+    let conflicts = conflict::find_conflicts(&mut transaction, user.id, id, &[user.id]).await?;
+
+    if mode.strict && !conflicts.is_empty()
+    {
+        return Err(ApiError::ScheduleConflict(conflicts));
+    }
+
+    transaction.commit().await?;
     
     Ok((
         StatusCode::CREATED,
         Json(json!({
-            "message": "Event created successfully.",
-            "id"     : event_result.id
+            "message"  : "Event created successfully.",
+            "id"       : id,
+            "conflicts": conflicts,
         })),
     ))
 }
 
 #[derive(Debug, FromRow, Serialize)]
-pub struct Schedule
+pub struct Event
 {
-    id           : i64,
-    recurrence_id: Option<i64>,
-    event_id     : i64,
-    start_time   : NaiveDateTime,
-    end_time     : NaiveDateTime,
+    pub id            : i64,
+    pub name          : String,
+    pub description   : String,
+    pub private       : bool,
+    pub super_event_id: Option<i64>,
+    pub time_zone     : String,
+    pub external_id   : Option<String>,
+    pub capacity      : Option<i32>,
 }
 
-pub async fn list_event_schedules(
-    Path(event_id): Path<i64>,
+impl Event
+{
+    /// Event time zones are only written from parsed ones, so they are always valid.
+    pub fn time_zone(&self) -> Tz
+    {
+        self.time_zone.parse().unwrap_or(Tz::UTC)
+    }
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct Participant
+{
+    pub id          : i64,
+    pub external_id : Option<String>,
+    pub name        : String,
+    pub status      : ParticipationStatus,
+    pub responded_at: Option<DateTime<Utc>>,
+    pub comment     : Option<String>,
+    pub owner       : bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EventDetails
+{
+    #[serde(flatten)]
+    pub event       : Event,
+    pub tags        : Vec<Tag>,
+    pub participants: Vec<Participant>,
+    pub attendance  : Attendance,
+    pub schedules   : Vec<Schedule>,
+    pub recurrences : Vec<Recurrence>,
+    pub sub_events  : Vec<Event>,
+}
+
+/// Private events are only listed for their participants.
+pub async fn list_events(
     State(db_pool): State<PgPool>,
-) -> Result<(StatusCode, Json<Vec<Schedule>>), (StatusCode, Json<Value>)>
+    CurrentUser(user): CurrentUser,
+) -> Result<(StatusCode, Json<Vec<Event>>), ApiError>
 {
-    let schedules = sqlx::query_as::<_, Schedule>
-        ("SELECT * FROM schedules WHERE event_id = $1
-        ORDER BY start_time")
-        .bind(event_id)
+    let events = sqlx::query_as::<_, Event>
+        ("SELECT * FROM events
+        WHERE NOT private OR EXISTS (
+            SELECT 1 FROM users_events WHERE users_events.event_id = events.id AND users_events.user_id = $1
+        )
+        ORDER BY id")
+        .bind(user.id)
         .fetch_all(&db_pool)
-        .await
-        .map_err(database_err_mapper)?;
+        .await?;
 
-    Ok((StatusCode::OK, Json(schedules)))
+    Ok((StatusCode::OK, Json(events)))
 }
 
-pub async fn list_user_schedules(
-    Path(user_id): Path<i64>,
+#[derive(Deserialize)]
+pub struct TimeZoneQuery
+{
+    pub tz: Option<Tz>,
+}
+
+pub async fn get_event(
     State(db_pool): State<PgPool>,
-) -> Result<(StatusCode, Json<Vec<Schedule>>), (StatusCode, Json<Value>)>
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+    Query(query): Query<TimeZoneQuery>,
+) -> Result<(StatusCode, Json<EventDetails>), ApiError>
 {
+    let time_zone = query.tz.unwrap_or(Tz::UTC);
+
+    policy::authorize_event(&db_pool, user.id, id, Action::View).await?;
+
+    let event = sqlx::query_as::<_, Event>
+        ("SELECT * FROM events WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&db_pool)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Event not found.".into()))?;
+
+    let tags = sqlx::query_as::<_, Tag>
+        ("SELECT tags.* FROM tags
+        JOIN events_tags ON events_tags.tag_id = tags.id
+        WHERE events_tags.event_id = $1
+        ORDER BY tags.name")
+        .bind(id)
+        .fetch_all(&db_pool)
+        .await?;
+
+    let participants = sqlx::query_as::<_, Participant>
+        ("SELECT users.id, users.external_id, users.name,
+            users_events.status, users_events.responded_at, users_events.comment, users_events.owner
+        FROM users
+        JOIN users_events ON users_events.user_id = users.id
+        WHERE users_events.event_id = $1
+        ORDER BY users_events.owner DESC, users.name")
+        .bind(id)
+        .fetch_all(&db_pool)
+        .await?;
+
+    let attendance = participants.iter().map(|participant| participant.status).collect();
+
     let schedules = sqlx::query_as::<_, Schedule>
-        ("SELECT id, recurrence_id, event_id, start_time, end_time FROM schedules
-        NATURAL JOIN users_events
-        WHERE user_id = $1
+        ("SELECT id, recurrence_id, event_id, start_time, end_time, original_start_time
+        FROM schedules WHERE event_id = $1
         ORDER BY start_time")
-        .bind(user_id)
+        .bind(id)
         .fetch_all(&db_pool)
-        .await
-        .map_err(database_err_mapper)?;
+        .await?
+        .into_iter()
+        .map(|schedule| schedule.in_time_zone(&time_zone))
+        .collect();
 
-    Ok((StatusCode::OK, Json(schedules)))
-}
+    let recurrences = recurrence::list_recurrences(&db_pool, &[id])
+        .await?
+        .into_iter()
+        .map(|recurrence| recurrence.in_time_zone(&time_zone))
+        .collect();
 
-pub async fn delete_schedule(
-    Path(id): Path<i64>,
-    State(db_pool): State<PgPool>,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
-{
-    let result = sqlx::query!("DELETE FROM schedules WHERE id = $1", id)
-        .execute(&db_pool)
-        .await
-        .map_err(database_err_mapper)?;
+    let sub_events = sqlx::query_as::<_, Event>
+        ("SELECT * FROM events
+        WHERE super_event_id = $1 AND (NOT private OR EXISTS (
+            SELECT 1 FROM users_events WHERE users_events.event_id = events.id AND users_events.user_id = $2
+        ))
+        ORDER BY id")
+        .bind(id)
+        .bind(user.id)
+        .fetch_all(&db_pool)
+        .await?;
 
-    match result.rows_affected()
-    {
-        0 => Ok((
-            StatusCode::NOT_FOUND,
-            Json(json!({"message": "Schedule not found."})),
-        )),
-        _ => Ok((
-            StatusCode::OK,
-            Json(json!({"message": "Schedule deleted successfully."})),
-        )),
-    }
+    Ok((
+        StatusCode::OK,
+        Json(EventDetails { event, tags, participants, attendance, schedules, recurrences, sub_events }),
+    ))
 }
 
-pub async fn delete_recurrence(
-    State(db_pool): State<PgPool>,
-    Path(id): Path<i64>
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+/// Distinguishes a field sent as `null` (`Some(None)`) from a missing one (`None`).
+fn deserialize_nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
 {
-    let result = sqlx::query!("DELETE FROM recurrences WHERE id = $1", id)
-        .execute(&db_pool)
-        .await
-        .map_err(database_err_mapper)?;
-
-    match result.rows_affected()
-    {
-        0 => Ok((
-            StatusCode::NOT_FOUND,
-            Json(json!({"message": "Recurrence not found."})),
-        )),
-        _ => Ok((
-            StatusCode::OK,
-            Json(json!({"message": "Schedules with recurrence deleted successfully."})),
-        )),
-    }
+    Option::<T>::deserialize(deserializer).map(Some)
 }
 
-#[derive(Deserialize)]
-pub struct AddUserToEventRequest
+#[derive(Deserialize, Validate)]
+pub struct UpdateEventRequest
 {
-    confirmation: Option<bool>,
-    owner       : Option<bool>,
+    #[validate(length(min = 1, max = 255, message = "must have between 1 and 255 characters"))]
+    pub name          : Option<String>,
+    #[validate(length(max = 255, message = "must have at most 255 characters"))]
+    pub description   : Option<String>,
+    pub private       : Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub super_event_id: Option<Option<i64>>,
+    pub time_zone     : Option<Tz>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    #[validate(range(min = 1, message = "must be at least 1"))]
+    pub capacity      : Option<Option<i32>>,
 }
 
-pub async fn add_user_to_event(
+/// Raising or removing the capacity promotes users from the waitlist.
+pub async fn update_event(
     State(db_pool): State<PgPool>,
-    Path((event_id, user_id)): Path<(i64, i64)>,
-    Json(req): Json<AddUserToEventRequest>
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+    State(channels): State<Channels>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+    ValidJson(req): ValidJson<UpdateEventRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
 {
-    let result = sqlx::query!(
-        "INSERT INTO users_events (user_id, event_id, confirmation, owner)
-        VALUES ($1, $2, $3, $4)",
-        user_id,
-        event_id,
-        req.confirmation.unwrap_or(false),
-        req.owner.unwrap_or(false)
-    )
-    .execute(&db_pool)
-    .await
-    .map_err(database_err_mapper)?;
-
-    match result.rows_affected()
+    if req.super_event_id == Some(Some(id))
     {
-        0 => Ok((
-            StatusCode::NOT_FOUND,
-            Json(json!({"message": "User or event not found."})),
-        )),
-        _ => Ok((
-            StatusCode::OK,
-            Json(json!({"message": "User added to event successfully."})),
-        )),
+        return Err(ApiError::BadRequest("An event can't be its own super event.".into()));
+    }
+
+    policy::authorize_event(&db_pool, user.id, id, Action::Edit).await?;
+
+    if let Some(Some(super_event_id)) = req.super_event_id
+    {
+        policy::authorize_event(&db_pool, user.id, super_event_id, Action::Edit).await?;
+    }
+
+    let mut transaction = db_pool.begin().await?;
+
+    if let Some(Some(super_event_id)) = req.super_event_id
+    {
+        let cycle = sqlx::query_scalar!(
+            r#"WITH RECURSIVE ancestors AS (
+                SELECT id, super_event_id FROM events WHERE id = $1
+                UNION
+                SELECT events.id, events.super_event_id FROM events JOIN ancestors ON events.id = ancestors.super_event_id
+            )
+            SELECT EXISTS (SELECT 1 FROM ancestors WHERE id = $2) AS "cycle!""#,
+            super_event_id,
+            id
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+
+        if cycle
+        {
+            return Err(ApiError::Unprocessable("An event can't be a sub event of its own sub events.".into()));
+        }
+    }
+
+    let result = sqlx::query!(
+        "UPDATE events SET
+            name           = COALESCE($1, name),
+            description    = COALESCE($2, description),
+            private        = COALESCE($3, private),
+            super_event_id = CASE WHEN $4 THEN $5 ELSE super_event_id END,
+            time_zone      = COALESCE($6, time_zone),
+            capacity       = CASE WHEN $7 THEN $8 ELSE capacity END
+        WHERE id = $9",
+        req.name,
+        req.description,
+        req.private,
+        req.super_event_id.is_some(),
+        req.super_event_id.flatten(),
+        req.time_zone.map(|time_zone| time_zone.name()),
+        req.capacity.is_some(),
+        req.capacity.flatten(),
+        id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    if result.rows_affected() == 0
+    {
+        return Err(ApiError::NotFound("Event not found.".into()));
     }
+
+    let promoted = waitlist::promote(&mut transaction, id).await?;
+
+    transaction.commit().await?;
+
+    waitlist::notify_promoted(&db_pool, &channels, id, &promoted).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"message": "Event updated successfully."})),
+    ))
 }
 
-pub async fn delete_user_from_event(
+/// Sub-events go with the event, and so do the files of all of them.
+pub async fn delete_event(
     State(db_pool): State<PgPool>,
-    Path((event_id, user_id)): Path<(i64, i64)>
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+    State(storage): State<SharedStorage>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
 {
-    let result = sqlx::query!(
-        "DELETE FROM users_events WHERE user_id = $1 AND event_id = $2",
-        user_id,
-        event_id
+    policy::authorize_event(&db_pool, user.id, id, Action::Delete).await?;
+
+    let mut transaction = db_pool.begin().await?;
+
+    let attachment_ids = sqlx::query_scalar!(
+        "WITH RECURSIVE tree AS (
+            SELECT id FROM events WHERE id = $1
+            UNION
+            SELECT events.id FROM events JOIN tree ON events.super_event_id = tree.id
+        )
+        DELETE FROM attachments WHERE event_id IN (SELECT id FROM tree) RETURNING id",
+        id
     )
-    .execute(&db_pool)
-    .await
-    .map_err(database_err_mapper)?;
+    .fetch_all(&mut *transaction)
+    .await?;
+
+    let result = sqlx::query!("DELETE FROM events WHERE id = $1", id)
+        .execute(&mut *transaction)
+        .await?;
+
+    if result.rows_affected() == 0
+    {
+        return Err(ApiError::NotFound("Event not found.".into()));
+    }
+
+    transaction.commit().await?;
+
+    attachment::delete_files(&storage, &attachment_ids).await;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"message": "Event deleted successfully."})),
+    ))
+}
+
+type Bounds = (DateTime<Utc>, DateTime<Utc>);
+
+/// Longest window of schedules expanded at once.
+const MAX_WINDOW_DAYS: i64 = 366;
+
+/// Window of a schedules listing, whose wall-clock bounds are read in the
+/// requested time zone, also used to render the schedules.
+#[derive(Deserialize)]
+pub struct ScheduleWindow
+{
+    pub from: Option<DateTimeInput>,
+    pub to  : Option<DateTimeInput>,
+    pub tz  : Option<Tz>,
+}
+
+impl ScheduleWindow
+{
+    /// Defaults to UTC.
+    pub fn time_zone(&self) -> Tz
+    {
+        self.tz.unwrap_or(Tz::UTC)
+    }
+
+    /// Defaults to the year starting now, and spans at most `MAX_WINDOW_DAYS`.
+    pub fn bounds(&self) -> Result<Bounds, ApiError>
+    {
+        let time_zone = self.time_zone();
+
+        let from = match self.from
+        {
+            Some(from) => resolve_time(from, &time_zone)?,
+            None => Utc::now(),
+        };
+        let to = match self.to
+        {
+            Some(to) => resolve_time(to, &time_zone)?,
+            None => from + Duration::days(365),
+        };
+
+        if to <= from
+        {
+            return Err(ApiError::Validation(validation::field_error(
+                "to",
+                validation::error("time_range", "must be after from"),
+            )));
+        }
+
+        if to - from > Duration::days(MAX_WINDOW_DAYS)
+        {
+            return Err(ApiError::Validation(validation::field_error(
+                "to",
+                validation::error("time_range", "must be at most 366 days after from"),
+            )));
+        }
+
+        Ok((from, to))
+    }
+}
+
+pub async fn list_event_schedules(
+    Path(event_id): Path<i64>,
+    Query(window): Query<ScheduleWindow>,
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+) -> Result<(StatusCode, Json<Vec<Schedule>>), ApiError>
+{
+    let (from, to) = window.bounds()?;
+    let time_zone  = window.time_zone();
+
+    policy::authorize_event(&db_pool, user.id, event_id, Action::View).await?;
+
+    let schedules = recurrence::list_schedules(&mut *db_pool.acquire().await?, &[event_id], from, to)
+        .await?
+        .into_iter()
+        .map(|schedule| schedule.in_time_zone(&time_zone))
+        .collect();
+
+    Ok((StatusCode::OK, Json(schedules)))
+}
+
+/// A schedule along with the answer of the user it is listed for.
+#[derive(Debug, Serialize)]
+pub struct UserSchedule
+{
+    #[serde(flatten)]
+    pub schedule: Schedule,
+    pub status  : ParticipationStatus,
+}
+
+/// Leaves out the private events the current user doesn't take part in.
+pub async fn list_user_schedules(
+    Path(user_id): Path<i64>,
+    Query(window): Query<ScheduleWindow>,
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+) -> Result<(StatusCode, Json<Vec<UserSchedule>>), ApiError>
+{
+    let (from, to) = window.bounds()?;
+    let time_zone  = window.time_zone();
+
+    let mut connection = db_pool.acquire().await?;
+
+    let responses = Responses::load(&mut connection, &[user_id]).await?;
+
+    let event_ids = policy::visible_event_ids(&mut *connection, user.id, &responses.event_ids())
+        .await?;
+
+    let schedules = recurrence::list_schedules(&mut connection, &event_ids, from, to)
+        .await?
+        .into_iter()
+        .filter_map(|schedule| {
+            let (_, status) = responses.statuses(&schedule).next()?;
+
+            Some(UserSchedule { schedule: schedule.in_time_zone(&time_zone), status })
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(schedules)))
+}
+
+/// Deleting an edited occurrence of a recurrence cancels it, instead of letting
+/// the recurrence generate it again.
+pub async fn delete_schedule(
+    Path(id): Path<i64>,
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    let event_id = sqlx::query_scalar!("SELECT event_id FROM schedules WHERE id = $1", id)
+        .fetch_optional(&db_pool)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Schedule not found.".into()))?;
+
+    policy::authorize_event(&db_pool, user.id, event_id, Action::Edit).await?;
+
+    let mut transaction = db_pool.begin().await?;
+
+    let deleted_schedule = sqlx::query!(
+        "DELETE FROM schedules WHERE id = $1
+        RETURNING recurrence_id, original_start_time",
+        id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+
+    let Some(deleted_schedule) = deleted_schedule else {
+        return Err(ApiError::NotFound("Schedule not found.".into()));
+    };
+
+    if let (Some(recurrence_id), Some(original_start_time)) = (deleted_schedule.recurrence_id, deleted_schedule.original_start_time)
+    {
+        sqlx::query!(
+            "UPDATE recurrences SET exdates = ARRAY_APPEND(exdates, $1) WHERE id = $2",
+            original_start_time,
+            recurrence_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"message": "Schedule deleted successfully."})),
+    ))
+}
+
+pub async fn delete_recurrence(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    let event_id = sqlx::query_scalar!("SELECT event_id FROM recurrences WHERE id = $1", id)
+        .fetch_optional(&db_pool)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Recurrence not found.".into()))?;
+
+    policy::authorize_event(&db_pool, user.id, event_id, Action::Edit).await?;
+
+    let result = sqlx::query!("DELETE FROM recurrences WHERE id = $1", id)
+        .execute(&db_pool)
+        .await?;
 
     match result.rows_affected()
     {
-        0 => Ok((
-            StatusCode::NOT_FOUND,
-            Json(json!({"message": "User or event not found."})),
-        )),
+        0 => Err(ApiError::NotFound("Recurrence not found.".into())),
         _ => Ok((
             StatusCode::OK,
-            Json(json!({"message": "User deleted from event successfully."})),
+            Json(json!({"message": "Schedules with recurrence deleted successfully."})),
         )),
     }
 }
 
-#[derive(Deserialize)]
-pub struct AddCommentToEventRequest
+async fn find_recurrence_or_not_found(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    id: i64,
+) -> Result<Recurrence, ApiError>
 {
-    pub user_id: i64,
-    pub title  : String,
-    pub content: String,
+    recurrence::find_recurrence(&mut **transaction, id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Recurrence not found.".into()))
 }
 
-pub async fn add_comment_to_event(
+/// Hands the answers to occurrences of a recurrence from `since` on over to
+/// another one, or to itself once rewritten, keeping those whose original start
+/// time it still generates and dropping the rest.
+async fn move_occurrence_responses(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    from_recurrence_id: i64,
+    since: DateTime<Utc>,
+    to_recurrence_id: i64,
+) -> Result<(), ApiError>
+{
+    let recurrence = find_recurrence_or_not_found(transaction, to_recurrence_id).await?;
+
+    let responses = sqlx::query!(
+        r#"SELECT id, original_start_time AS "original_start_time!"
+        FROM occurrence_responses WHERE recurrence_id = $1 AND original_start_time >= $2"#,
+        from_recurrence_id,
+        since
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+
+    let (mut kept, mut dropped) = (Vec::new(), Vec::new());
+
+    for response in responses
+    {
+        match recurrence.has_occurrence(response.original_start_time)?
+        {
+            true  => kept.push(response.id),
+            false => dropped.push(response.id),
+        }
+    }
+
+    sqlx::query!(
+        "UPDATE occurrence_responses SET recurrence_id = $1 WHERE id = ANY($2)",
+        to_recurrence_id,
+        &kept
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    sqlx::query!("DELETE FROM occurrence_responses WHERE id = ANY($1)", &dropped)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+pub fn occurrence_not_found() -> ApiError
+{
+    ApiError::NotFound("Occurrence not found.".into())
+}
+
+/// Cancels a single occurrence of a recurrence, identified by its original start time.
+pub async fn delete_occurrence(
     State(db_pool): State<PgPool>,
-    Path(id): Path<i64>,
-    Json(req): Json<AddCommentToEventRequest>,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+    CurrentUser(user): CurrentUser,
+    Path((id, occurrence)): Path<(i64, DateTimeInput)>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
 {
-    let comment_result = sqlx::query!(
-        "INSERT INTO events_comments (event_id, user_id, title, content)
-        VALUES ($1, $2, $3, $4) RETURNING id",
+    let mut transaction = db_pool.begin().await?;
+
+    let recurrence = find_recurrence_or_not_found(&mut transaction, id).await?;
+    policy::authorize_event(&mut *transaction, user.id, recurrence.event_id, Action::Edit).await?;
+    let occurrence = resolve_time(occurrence, &recurrence.time_zone())?;
+
+    let result = sqlx::query!(
+        "DELETE FROM schedules WHERE recurrence_id = $1 AND original_start_time = $2",
         id,
-        req.user_id,
-        req.title,
-        req.content
+        occurrence
     )
-    .fetch_optional(&db_pool)
-    .await
-    .map_err(database_err_mapper)?;
+    .execute(&mut *transaction)
+    .await?;
 
-    match comment_result
+    if result.rows_affected() == 0 && !recurrence.has_occurrence(occurrence)?
     {
-        None => Ok((
-            StatusCode::NOT_FOUND,
-            Json(json!({"message": "Event not found."})),
-        )),
-        Some(comment) => Ok((
-            StatusCode::OK,
-            Json(json!({
-                "message": "Comment added to event successfully.",
-                "id": comment.id
-            })),
-        )),
+        return Err(occurrence_not_found());
     }
+
+    sqlx::query!(
+        "UPDATE recurrences SET exdates = ARRAY_APPEND(exdates, $1) WHERE id = $2",
+        occurrence,
+        id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"message": "Occurrence deleted successfully."})),
+    ))
 }
 
 #[derive(Deserialize)]
-pub struct UpdateCommentRequest
+#[serde(rename_all = "lowercase", tag = "scope")]
+pub enum UpdateRecurrenceRequest
+{
+    Occurrence {
+        occurrence: DateTimeInput,
+        start_time: DateTimeInput,
+        end_time  : DateTimeInput,
+    },
+    Following {
+        occurrence   : DateTimeInput,
+        configuration: EventConfiguration,
+    },
+    All {
+        configuration: EventConfiguration,
+    },
+}
+
+impl Validate for UpdateRecurrenceRequest
+{
+    fn validate(&self) -> Result<(), ValidationErrors>
+    {
+        match self
+        {
+            UpdateRecurrenceRequest::Occurrence { start_time, end_time, .. } =>
+            {
+                let mut errors = ValidationErrors::new();
+                validation::time_range(&mut errors, start_time, end_time);
+
+                match errors.is_empty()
+                {
+                    true  => Ok(()),
+                    false => Err(errors),
+                }
+            },
+            UpdateRecurrenceRequest::Following { configuration, .. }
+            | UpdateRecurrenceRequest::All { configuration } =>
+                ValidationErrors::merge(Ok(()), "configuration", configuration.validate()),
+        }
+    }
+}
+
+fn recurring_configuration(
+    configuration: EventConfiguration,
+    time_zone: &Tz,
+) -> Result<RecurrenceRule, ApiError>
 {
-    pub title  : String,
-    pub content: String,
+    match parse_configuration(configuration, time_zone)?
+    {
+        ConfiguredSchedule::Recurring(rule) => Ok(rule),
+        ConfiguredSchedule::Individual(..) => Err(ApiError::BadRequest("A recurrence can only be updated with a recurring configuration.".into())),
+    }
 }
 
-pub async fn update_comment(
+/// Edits a recurrence either for a single occurrence (identified by its original
+/// start time), for that occurrence and all the following ones, which splits the
+/// series in a new recurrence, or for the whole series, discarding its exceptions.
+/// Answers to single occurrences follow them, and go when they do.
+pub async fn update_recurrence(
     State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
     Path(id): Path<i64>,
-    Json(req): Json<UpdateCommentRequest>,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+    ValidJson(req): ValidJson<UpdateRecurrenceRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
 {
+    let mut transaction = db_pool.begin().await?;
+
+    let recurrence = find_recurrence_or_not_found(&mut transaction, id).await?;
+    policy::authorize_event(&mut *transaction, user.id, recurrence.event_id, Action::Edit).await?;
+    let time_zone  = recurrence.time_zone();
+
+    let recurrence_id = match req
+    {
+        UpdateRecurrenceRequest::Occurrence { occurrence, start_time, end_time } =>
+        {
+            let occurrence = resolve_time(occurrence, &time_zone)?;
+            let start_time = resolve_time(start_time, &time_zone)?;
+            let end_time   = resolve_time(end_time, &time_zone)?;
+
+            check_time_range(start_time, end_time)?;
+
+            let result = sqlx::query!(
+                "UPDATE schedules SET start_time = $1, end_time = $2
+                WHERE recurrence_id = $3 AND original_start_time = $4",
+                start_time,
+                end_time,
+                id,
+                occurrence
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            if result.rows_affected() == 0
+            {
+                if !recurrence.has_occurrence(occurrence)?
+                {
+                    return Err(occurrence_not_found());
+                }
+
+                sqlx::query!(
+                    "INSERT INTO schedules (event_id, recurrence_id, start_time, end_time, original_start_time)
+                    VALUES ($1, $2, $3, $4, $5)",
+                    recurrence.event_id,
+                    id,
+                    start_time,
+                    end_time,
+                    occurrence
+                )
+                .execute(&mut *transaction)
+                .await?;
+            }
+
+            id
+        },
+        UpdateRecurrenceRequest::Following { occurrence, configuration } =>
+        {
+            let occurrence = resolve_time(occurrence, &time_zone)?;
+            let rule       = recurring_configuration(configuration, &time_zone)?;
+
+            if !recurrence.has_occurrence(occurrence)?
+            {
+                return Err(occurrence_not_found());
+            }
+
+            let new_recurrence_id = create_recurrence(&mut transaction, recurrence.event_id, &rule)
+                .await?;
+
+            move_occurrence_responses(&mut transaction, id, occurrence, new_recurrence_id).await?;
+
+            if occurrence <= recurrence.start_time
+            {
+                sqlx::query!("DELETE FROM recurrences WHERE id = $1", id)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            else
+            {
+                // the original series is capped right before the split occurrence
+                sqlx::query!(
+                    "UPDATE recurrences SET end_date = LEAST(end_date, $1) WHERE id = $2",
+                    occurrence - Duration::seconds(1),
+                    id
+                )
+                .execute(&mut *transaction)
+                .await?;
+
+                sqlx::query!(
+                    "DELETE FROM schedules WHERE recurrence_id = $1 AND original_start_time >= $2",
+                    id,
+                    occurrence
+                )
+                .execute(&mut *transaction)
+                .await?;
+            }
+
+            new_recurrence_id
+        },
+        UpdateRecurrenceRequest::All { configuration } =>
+        {
+            let rule = recurring_configuration(configuration, &time_zone)?;
+
+            sqlx::query!("DELETE FROM schedules WHERE recurrence_id = $1", id)
+                .execute(&mut *transaction)
+                .await?;
+
+            sqlx::query!("DELETE FROM recurrences_week_days WHERE recurrence_id = $1", id)
+                .execute(&mut *transaction)
+                .await?;
+
+            sqlx::query!(
+                "UPDATE recurrences SET type = $1, step = $2, repetitions = $3, end_date = $4,
+                    rrule = $5, start_time = $6, end_time = $7, exdates = $8
+                WHERE id = $9",
+                rule.recurrence_type as RecurrenceType,
+                rule.step,
+                rule.repetitions,
+                rule.end_date,
+                rule.rrule,
+                rule.start_time,
+                rule.end_time,
+                &rule.exdates,
+                id
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            create_recurrence_week_days(&mut transaction, id, &rule.days_of_week)
+                .await?;
+
+            move_occurrence_responses(&mut transaction, id, recurrence.start_time.to_utc(), id).await?;
+
+            id
+        },
+    };
+
+    transaction.commit().await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": "Recurrence updated successfully.",
+            "id"     : recurrence_id
+        })),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct AddUserToEventRequest
+{
+    /// Only for users adding themselves, others are asked.
+    status: Option<ParticipationStatus>,
+    owner : Option<bool>,
+}
+
+/// Users can join public events by themselves, anything else is up to the owners.
+/// Warns about the schedules that overlap others of the user, or rejects them
+/// in strict mode. Users that would take a place of a full event wait for one
+/// instead, but owners.
+pub async fn add_user_to_event(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path((event_id, user_id)): Path<(i64, i64)>,
+    Query(mode): Query<ConflictMode>,
+    Json(req): Json<AddUserToEventRequest>
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    let action = match user_id == user.id && !req.owner.unwrap_or(false)
+    {
+        true  => Action::Join,
+        false => Action::ManageParticipants,
+    };
+
+    policy::authorize_event(&db_pool, user.id, event_id, action).await?;
+
+    if req.status.is_some() && user_id != user.id
+    {
+        return Err(ApiError::Validation(validation::field_error(
+            "status",
+            validation::error("self_only", "can only be given when adding oneself"),
+        )));
+    }
+
+    let status = req.status.unwrap_or(ParticipationStatus::NeedsAction);
+    let owner  = req.owner.unwrap_or(false);
+
+    let mut transaction = db_pool.begin().await?;
+
+    let places = Places::lock(&mut transaction, event_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Event not found.".into()))?;
+
+    if places.full() && !owner && status != ParticipationStatus::Declined
+    {
+        let position = waitlist::enqueue(&mut transaction, event_id, user_id, status).await?;
+
+        transaction.commit().await?;
+
+        return Ok((
+            StatusCode::ACCEPTED,
+            Json(json!({
+                "message" : "The event is full, user added to the waitlist.",
+                "position": position,
+            })),
+        ));
+    }
+
     let result = sqlx::query!(
-        "UPDATE events_comments SET title = $1, content = $2
-        WHERE id = $3",
-        req.title,
-        req.content,
-        id
+        "INSERT INTO users_events (user_id, event_id, status, responded_at, owner)
+        VALUES ($1, $2, $3, CASE WHEN $3 <> 'needs_action'::participation_status THEN NOW() END, $4)",
+        user_id,
+        event_id,
+        status as ParticipationStatus,
+        owner
     )
-    .execute(&db_pool)
-    .await
-    .map_err(database_err_mapper)?;
+    .execute(&mut *transaction)
+    .await?;
 
-    match result.rows_affected()
+    if result.rows_affected() == 0
     {
-        0 => Ok((
-            StatusCode::NOT_FOUND,
-            Json(json!({"message": "Comment not found."})),
-        )),
-        _ => Ok((
-            StatusCode::OK,
-            Json(json!({"message": "Comment updated successfully."})),
-        )),
+        return Err(ApiError::NotFound("User or event not found.".into()));
     }
+
+    let conflicts = conflict::find_conflicts(&mut transaction, user.id, event_id, &[user_id]).await?;
+
+    if mode.strict && !conflicts.is_empty()
+    {
+        return Err(ApiError::ScheduleConflict(conflicts));
+    }
+
+    transaction.commit().await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message"  : "User added to event successfully.",
+            "conflicts": conflicts,
+        })),
+    ))
 }
 
-pub async fn delete_comment(
+/// Takes users out of the event or of its waitlist, promoting the first one
+/// waiting to the place left.
+pub async fn delete_user_from_event(
     State(db_pool): State<PgPool>,
-    Path(id): Path<i64>,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+    State(channels): State<Channels>,
+    CurrentUser(user): CurrentUser,
+    Path((event_id, user_id)): Path<(i64, i64)>
+) -> Result<(StatusCode, Json<Value>), ApiError>
 {
-    let result = sqlx::query!
-        ("DELETE FROM events_comments WHERE id = $1", id)
+    // users waiting for a place don't take part yet, but can stop waiting
+    if user_id == user.id
+    {
+        let result = sqlx::query!(
+            "DELETE FROM waitlist WHERE user_id = $1 AND event_id = $2",
+            user_id,
+            event_id
+        )
         .execute(&db_pool)
-        .await
-        .map_err(database_err_mapper)?;
+        .await?;
 
-    match result.rows_affected()
+        if result.rows_affected() > 0
+        {
+            return Ok((
+                StatusCode::OK,
+                Json(json!({"message": "User deleted from the waitlist of the event successfully."})),
+            ));
+        }
+    }
+
+    let action = match user_id == user.id
     {
-        0 => Ok((
-            StatusCode::NOT_FOUND,
-            Json(json!({"message": "Comment not found."})),
-        )),
-        _ => Ok((
-            StatusCode::OK,
-            Json(json!({"message": "Comment deleted successfully."})),
-        )),
+        true  => Action::Leave,
+        false => Action::ManageParticipants,
+    };
+
+    policy::authorize_event(&db_pool, user.id, event_id, action).await?;
+
+    let mut transaction = db_pool.begin().await?;
+
+    let result = sqlx::query!(
+        "DELETE FROM users_events WHERE user_id = $1 AND event_id = $2",
+        user_id,
+        event_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    if result.rows_affected() == 0
+    {
+        let result = sqlx::query!(
+            "DELETE FROM waitlist WHERE user_id = $1 AND event_id = $2",
+            user_id,
+            event_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        return match result.rows_affected()
+        {
+            0 => Err(ApiError::NotFound("User or event not found.".into())),
+            _ =>
+            {
+                transaction.commit().await?;
+
+                Ok((
+                    StatusCode::OK,
+                    Json(json!({"message": "User deleted from the waitlist of the event successfully."})),
+                ))
+            },
+        };
     }
+
+    let promoted = waitlist::promote(&mut transaction, event_id).await?;
+
+    transaction.commit().await?;
+
+    waitlist::notify_promoted(&db_pool, &channels, event_id, &promoted).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"message": "User deleted from event successfully."})),
+    ))
 }
\ No newline at end of file
@@ -0,0 +1,491 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection, PgPool};
+use serde_json::{json, Value};
+use chrono::{DateTime, Utc};
+use validator::{Validate, ValidateEmail, ValidationErrors};
+use crate::{
+    handlers::{participation::ParticipationStatus, user::ReminderType, waitlist::{self, Places}},
+    helpers::{
+        auth::{CurrentUser, InvitationTokens},
+        error::ApiError,
+        extract::{Json, Path, ValidJson},
+        policy::{self, Action},
+        template::{escape_html, render},
+        validation,
+    },
+    notifications::{self, Channels, Message},
+};
+
+/// How long invitations can be answered.
+const INVITATION_TTL_DAYS: i32 = 14;
+
+const INVITATION_PAGE: &str = include_str!("templates/invitation.html");
+
+#[derive(Debug, Clone, Copy, Serialize, sqlx::Type, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "invitation_status", rename_all = "lowercase")]
+pub enum InvitationStatus
+{
+    Pending,
+    Accepted,
+    Declined,
+    Revoked,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct Invitation
+{
+    pub id        : i64,
+    pub event_id  : i64,
+    pub event_name: String,
+    pub inviter_id: Option<i64>,
+    pub user_id   : Option<i64>,
+    pub email     : Option<String>,
+    pub status    : InvitationStatus,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Only given to the invitee.
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token     : Option<String>,
+}
+
+const SELECT_INVITATIONS: &str =
+    "SELECT invitations.id, invitations.event_id, events.name AS event_name, invitations.inviter_id,
+        invitations.user_id, invitations.email, invitations.status, invitations.created_at, invitations.expires_at
+    FROM invitations
+    JOIN events ON events.id = invitations.event_id";
+
+/// Invites either a user or an email address.
+#[derive(Deserialize)]
+pub struct InviteRequest
+{
+    pub user_id: Option<i64>,
+    pub email  : Option<String>,
+}
+
+impl Validate for InviteRequest
+{
+    fn validate(&self) -> Result<(), ValidationErrors>
+    {
+        let (field, error) = match (self.user_id, &self.email)
+        {
+            (Some(_), None) => return Ok(()),
+            (None, Some(email)) if !email.validate_email() =>
+                ("email", validation::error("email", "must be an email address")),
+            (None, Some(email)) if email.chars().count() > 255 =>
+                ("email", validation::error("length", "must have at most 255 characters")),
+            (None, Some(_)) => return Ok(()),
+            _ => ("user_id", validation::error("invitee", "must give either user_id or email")),
+        };
+
+        Err(validation::field_error(field, error))
+    }
+}
+
+/// Sends the invitee a link to answer with, to their contacts or to the
+/// invited address. Addresses of registered users invite those users.
+pub async fn invite_to_event(
+    State(db_pool): State<PgPool>,
+    State(tokens): State<InvitationTokens>,
+    State(channels): State<Channels>,
+    CurrentUser(user): CurrentUser,
+    Path(event_id): Path<i64>,
+    ValidJson(req): ValidJson<InviteRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    policy::authorize_event(&db_pool, user.id, event_id, Action::ManageParticipants).await?;
+
+    let user_id = match (req.user_id, &req.email)
+    {
+        (Some(user_id), _) => Some(user_id),
+        (None, Some(email)) => sqlx::query_scalar!(
+            "SELECT user_id FROM users_contacts
+            WHERE type = 'email' AND LOWER(contact) = LOWER($1)
+            ORDER BY id LIMIT 1",
+            email
+        )
+        .fetch_optional(&db_pool)
+        .await?,
+        (None, None) => None,
+    };
+
+    let email = match user_id
+    {
+        Some(_) => None,
+        None => req.email,
+    };
+
+    if let Some(user_id) = user_id
+    {
+        let participant = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM users_events WHERE user_id = $1 AND event_id = $2) AS "exists!""#,
+            user_id,
+            event_id
+        )
+        .fetch_one(&db_pool)
+        .await?;
+
+        if participant
+        {
+            return Err(ApiError::Conflict("User already takes part in the event.".into()));
+        }
+    }
+
+    let invitation = sqlx::query!(
+        "INSERT INTO invitations (event_id, inviter_id, user_id, email, expires_at)
+        VALUES ($1, $2, $3, $4, NOW() + MAKE_INTERVAL(days => $5))
+        RETURNING id, expires_at",
+        event_id,
+        user.id,
+        user_id,
+        email,
+        INVITATION_TTL_DAYS
+    )
+    .fetch_one(&db_pool)
+    .await?;
+
+    let token   = tokens.sign(invitation.id, invitation.expires_at);
+    let message = Message::Invitation {
+        event      : notifications::notified_event(&db_pool, event_id).await?,
+        inviter    : user.name,
+        accept_url : tokens.link(&token, "accept"),
+        decline_url: tokens.link(&token, "decline"),
+        expires_at : invitation.expires_at,
+    };
+
+    match (user_id, email)
+    {
+        (Some(user_id), _) => notifications::notify_user(&db_pool, &channels, user_id, message).await?,
+        (None, Some(email)) => notifications::notify(&channels, vec![(ReminderType::Email, email)], message),
+        (None, None) => {},
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "message": "Invitation sent successfully.",
+            "id"     : invitation.id
+        })),
+    ))
+}
+
+/// Pending invitations of an event, for its owners.
+pub async fn list_event_invitations(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path(event_id): Path<i64>,
+) -> Result<(StatusCode, Json<Vec<Invitation>>), ApiError>
+{
+    policy::authorize_event(&db_pool, user.id, event_id, Action::ManageParticipants).await?;
+
+    let invitations = sqlx::query_as::<_, Invitation>(&format!(
+        "{SELECT_INVITATIONS}
+        WHERE invitations.event_id = $1 AND invitations.status = 'pending' AND invitations.expires_at > NOW()
+        ORDER BY invitations.created_at"))
+        .bind(event_id)
+        .fetch_all(&db_pool)
+        .await?;
+
+    Ok((StatusCode::OK, Json(invitations)))
+}
+
+/// Pending invitations of the current user, also the ones sent to their email
+/// addresses, with the tokens to answer them.
+pub async fn list_user_invitations(
+    State(db_pool): State<PgPool>,
+    State(tokens): State<InvitationTokens>,
+    CurrentUser(user): CurrentUser,
+) -> Result<(StatusCode, Json<Vec<Invitation>>), ApiError>
+{
+    let invitations = sqlx::query_as::<_, Invitation>(&format!(
+        "{SELECT_INVITATIONS}
+        WHERE invitations.status = 'pending' AND invitations.expires_at > NOW()
+            AND (invitations.user_id = $1 OR (invitations.user_id IS NULL AND LOWER(invitations.email) IN (
+                SELECT LOWER(contact) FROM users_contacts WHERE user_id = $1 AND type = 'email'
+            )))
+        ORDER BY invitations.created_at"))
+        .bind(user.id)
+        .fetch_all(&db_pool)
+        .await?
+        .into_iter()
+        .map(|invitation| Invitation {
+            token: Some(tokens.sign(invitation.id, invitation.expires_at)),
+            ..invitation
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(invitations)))
+}
+
+pub async fn revoke_invitation(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    let event_id = sqlx::query_scalar!(
+        "SELECT event_id FROM invitations WHERE id = $1 AND status = 'pending'",
+        id
+    )
+    .fetch_optional(&db_pool)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Invitation not found.".into()))?;
+
+    policy::authorize_event(&db_pool, user.id, event_id, Action::ManageParticipants).await?;
+
+    sqlx::query!(
+        "UPDATE invitations SET status = 'revoked' WHERE id = $1 AND status = 'pending'",
+        id
+    )
+    .execute(&db_pool)
+    .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"message": "Invitation revoked successfully."})),
+    ))
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum InvitationAnswer
+{
+    Accept,
+    Decline,
+}
+
+/// Gives invitees a place the way joining does, returning the waitlist
+/// position of the ones accepting a full event, where those already waiting
+/// keep theirs. Declining participants keep their row, but not their place,
+/// while other users declining aren't added, as that would let them see the
+/// event when it's private. Either way they stop waiting.
+async fn answer_participation(
+    connection: &mut PgConnection,
+    event_id: i64,
+    user_id: i64,
+    status: ParticipationStatus,
+) -> Result<Option<i64>, ApiError>
+{
+    let places = Places::lock(&mut *connection, event_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Event not found.".into()))?;
+
+    let previous = sqlx::query!(
+        r#"SELECT status AS "status: ParticipationStatus", owner
+        FROM users_events WHERE user_id = $1 AND event_id = $2"#,
+        user_id,
+        event_id
+    )
+    .fetch_optional(&mut *connection)
+    .await?;
+
+    let has_place = previous.as_ref()
+        .is_some_and(|previous| previous.owner || previous.status != ParticipationStatus::Declined);
+
+    if status != ParticipationStatus::Declined && !has_place && places.full()
+    {
+        if previous.is_some()
+        {
+            return Err(ApiError::Conflict("The event is full.".into()));
+        }
+
+        let position = sqlx::query_scalar!(
+            r#"UPDATE waitlist SET status = $3 WHERE event_id = $1 AND user_id = $2
+            RETURNING (SELECT COUNT(*) FROM waitlist AS ahead WHERE ahead.event_id = $1 AND ahead.id <= waitlist.id) AS "position!""#,
+            event_id,
+            user_id,
+            status as ParticipationStatus
+        )
+        .fetch_optional(&mut *connection)
+        .await?;
+
+        return match position
+        {
+            Some(position) => Ok(Some(position)),
+            None           => Ok(Some(waitlist::enqueue(connection, event_id, user_id, status).await?)),
+        };
+    }
+
+    sqlx::query!("DELETE FROM waitlist WHERE event_id = $1 AND user_id = $2", event_id, user_id)
+        .execute(&mut *connection)
+        .await?;
+
+    if status == ParticipationStatus::Declined && previous.is_none()
+    {
+        return Ok(None);
+    }
+
+    sqlx::query!(
+        "INSERT INTO users_events (user_id, event_id, status, responded_at)
+        VALUES ($1, $2, $3, NOW())
+        ON CONFLICT (user_id, event_id) DO UPDATE SET status = EXCLUDED.status, responded_at = NOW()",
+        user_id,
+        event_id,
+        status as ParticipationStatus
+    )
+    .execute(connection)
+    .await?;
+
+    Ok(None)
+}
+
+/// The confirmation page of an answer, for the heading, message and button
+/// it's given, hiding the button when there is nothing left to confirm.
+fn invitation_page(status: StatusCode, subtitle: &str, heading: &str, message: &str, button: Option<&str>) -> Response
+{
+    let page = render(INVITATION_PAGE, &[
+        ("subtitle", escape_html(subtitle)),
+        ("heading", escape_html(heading)),
+        ("message", escape_html(message)),
+        ("button", escape_html(button.unwrap_or_default())),
+        ("hidden", if button.is_some() { String::new() } else { "hidden".to_owned() }),
+    ]);
+
+    (status, Html(page)).into_response()
+}
+
+/// Serves the page the links sent to invitees open, which asks them to confirm
+/// their answer and posts it, so opening a link (as link previews and mail
+/// scanners do) never answers an invitation.
+pub async fn confirm_invitation(
+    State(db_pool): State<PgPool>,
+    State(tokens): State<InvitationTokens>,
+    Path((token, answer)): Path<(String, InvitationAnswer)>,
+) -> Response
+{
+    let invitation = match tokens.verify(&token)
+    {
+        Ok(id) => sqlx::query!(
+            r#"SELECT invitations.user_id, invitations.status AS "status: InvitationStatus",
+                events.name AS event_name, inviters.name AS "inviter?"
+            FROM invitations
+            JOIN events ON events.id = invitations.event_id
+            LEFT JOIN users AS inviters ON inviters.id = invitations.inviter_id
+            WHERE invitations.id = $1"#,
+            id
+        )
+        .fetch_optional(&db_pool)
+        .await
+        .map_err(ApiError::from)
+        .and_then(|invitation| invitation.ok_or_else(|| ApiError::NotFound("Invitation not found.".into()))),
+        Err(e) => Err(e),
+    };
+
+    let invitation = match invitation
+    {
+        Ok(invitation) => invitation,
+        Err(e) => return invitation_page(e.status(), "", "Invitation", &e.to_string(), None),
+    };
+
+    let subtitle = match &invitation.inviter
+    {
+        Some(inviter) => format!("{inviter} invited you to"),
+        None => "You were invited to".to_owned(),
+    };
+
+    let (message, button) = match (invitation.status, answer)
+    {
+        (InvitationStatus::Accepted, _) => ("The invitation was already accepted.", None),
+        (InvitationStatus::Declined, _) => ("The invitation was already declined.", None),
+        (InvitationStatus::Revoked, _)  => ("The invitation was revoked.", None),
+        (InvitationStatus::Pending, InvitationAnswer::Accept) if invitation.user_id.is_none() =>
+            ("Sign in to accept an invitation sent by email.", None),
+        (InvitationStatus::Pending, InvitationAnswer::Accept)  => ("Do you want to accept the invitation?", Some("Accept")),
+        (InvitationStatus::Pending, InvitationAnswer::Decline) => ("Do you want to decline the invitation?", Some("Decline")),
+    };
+
+    invitation_page(StatusCode::OK, &subtitle, &invitation.event_name, message, button)
+}
+
+/// Answers with the token sent to the invitee, without signing in. Invitations
+/// sent to an email address are accepted by the signed in user, who takes part
+/// in the event from then on, or waits for a place when it is full. Declining
+/// promotes the first user waiting to the place left.
+pub async fn answer_invitation(
+    State(db_pool): State<PgPool>,
+    State(channels): State<Channels>,
+    State(tokens): State<InvitationTokens>,
+    current_user: Option<CurrentUser>,
+    Path((token, answer)): Path<(String, InvitationAnswer)>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    let id = tokens.verify(&token)?;
+
+    let mut transaction = db_pool.begin().await?;
+
+    let invitation = sqlx::query!(
+        r#"SELECT event_id, user_id, status AS "status: InvitationStatus"
+        FROM invitations WHERE id = $1
+        FOR UPDATE"#,
+        id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Invitation not found.".into()))?;
+
+    match invitation.status
+    {
+        InvitationStatus::Pending  => {},
+        InvitationStatus::Accepted => return Err(ApiError::Conflict("The invitation was already accepted.".into())),
+        InvitationStatus::Declined => return Err(ApiError::Conflict("The invitation was already declined.".into())),
+        InvitationStatus::Revoked  => return Err(ApiError::Conflict("The invitation was revoked.".into())),
+    }
+
+    let user_id = invitation.user_id.or(current_user.map(|CurrentUser(user)| user.id));
+
+    let (status, participation_status, message) = match answer
+    {
+        InvitationAnswer::Accept =>
+            (InvitationStatus::Accepted, ParticipationStatus::Accepted, "Invitation accepted successfully."),
+        InvitationAnswer::Decline =>
+            (InvitationStatus::Declined, ParticipationStatus::Declined, "Invitation declined successfully."),
+    };
+
+    let (position, promoted) = match user_id
+    {
+        Some(user_id) =>
+        {
+            let position = answer_participation(&mut transaction, invitation.event_id, user_id, participation_status).await?;
+
+            (position, waitlist::promote(&mut transaction, invitation.event_id).await?)
+        },
+        None if answer == InvitationAnswer::Accept =>
+            return Err(ApiError::Unauthorized("Sign in to accept an invitation sent by email.".into())),
+        None => (None, Vec::new()),
+    };
+
+    sqlx::query!(
+        "UPDATE invitations SET status = $1, responded_at = NOW(), user_id = COALESCE(user_id, $2)
+        WHERE id = $3",
+        status as InvitationStatus,
+        user_id,
+        id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    waitlist::notify_promoted(&db_pool, &channels, invitation.event_id, &promoted).await?;
+
+    match position
+    {
+        Some(position) => Ok((
+            StatusCode::ACCEPTED,
+            Json(json!({
+                "message" : "Invitation accepted, the event is full and the user waits for a place.",
+                "position": position,
+            })),
+        )),
+        None => Ok((
+            StatusCode::OK,
+            Json(json!({"message": message})),
+        )),
+    }
+}
@@ -0,0 +1,225 @@
+use std::{collections::BTreeSet, sync::LazyLock};
+use axum::{extract::State, http::StatusCode};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection, PgPool};
+use chrono::{DateTime, Utc};
+use validator::Validate;
+use crate::{
+    handlers::user::User,
+    helpers::{
+        auth::CurrentUser,
+        error::ApiError,
+        extract::{Json, Path, ValidQuery},
+    },
+    notifications::{self, Channels, Message},
+};
+
+const DEFAULT_MENTIONS_PER_PAGE: i64 = 20;
+
+/// `@` followed by an id or an external id, like `@42` or `@auth0|42`, but
+/// not inside words like email addresses.
+static MENTION_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?:^|[^\w@])@([\w.|:-]+)").unwrap());
+
+/// The ids and external ids mentioned in a text, without the punctuation
+/// that may follow them.
+fn parse_mentions(text: &str) -> BTreeSet<&str>
+{
+    MENTION_REGEX.captures_iter(text)
+        .filter_map(|captures| captures.get(1))
+        .map(|mention| mention.as_str().trim_end_matches(['.', ':']))
+        .filter(|mention| !mention.is_empty())
+        .collect()
+}
+
+/// Keeps the users mentioned in a comment, but its author, returning the ones
+/// that weren't before. Private events only let their participants be
+/// mentioned, and `@` words matching no user are ignored.
+pub async fn save_mentions(
+    connection: &mut PgConnection,
+    comment_id: i64,
+    event_id: i64,
+    author_id: i64,
+    content: &str,
+) -> Result<Vec<i64>, ApiError>
+{
+    let mentions = parse_mentions(content);
+
+    let ids: Vec<i64>           = mentions.iter().filter_map(|mention| mention.parse().ok()).collect();
+    let external_ids: Vec<&str> = mentions.into_iter().collect();
+
+    let mentioned = sqlx::query!(
+        r#"SELECT users.id, events.private AND users_events.user_id IS NULL AS "outsider!"
+        FROM users
+        JOIN events ON events.id = $3
+        LEFT JOIN users_events ON users_events.user_id = users.id AND users_events.event_id = events.id
+        WHERE (users.id = ANY($1) OR users.external_id = ANY($2)) AND users.id <> $4
+        ORDER BY users.id"#,
+        &ids,
+        &external_ids as &[&str],
+        event_id,
+        author_id
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+
+    let outsiders: Vec<String> = mentioned.iter()
+        .filter(|user| user.outsider)
+        .map(|user| user.id.to_string())
+        .collect();
+
+    if !outsiders.is_empty()
+    {
+        return Err(ApiError::Unprocessable(format!(
+            "Mentioned users don't take part in the event: {}.",
+            outsiders.join(", ")
+        )));
+    }
+
+    let user_ids: Vec<i64> = mentioned.iter().map(|user| user.id).collect();
+
+    sqlx::query!(
+        "DELETE FROM events_comments_mentions WHERE comment_id = $1 AND NOT user_id = ANY($2)",
+        comment_id,
+        &user_ids
+    )
+    .execute(&mut *connection)
+    .await?;
+
+    let added = sqlx::query_scalar!(
+        "INSERT INTO events_comments_mentions (comment_id, user_id)
+        SELECT $1, UNNEST($2::BIGINT[])
+        ON CONFLICT (comment_id, user_id) DO NOTHING
+        RETURNING user_id",
+        comment_id,
+        &user_ids
+    )
+    .fetch_all(connection)
+    .await?;
+
+    Ok(added)
+}
+
+/// Tells the users a comment mentions, once it is committed, through their
+/// preferred contact.
+pub async fn notify_mentioned(
+    db_pool: &PgPool,
+    channels: &Channels,
+    event_id: i64,
+    author: &str,
+    title: &str,
+    content: &str,
+    user_ids: &[i64],
+) -> Result<(), sqlx::Error>
+{
+    if user_ids.is_empty()
+    {
+        return Ok(());
+    }
+
+    let event = notifications::notified_event(db_pool, event_id).await?;
+
+    for user_id in user_ids
+    {
+        let message = Message::Mention {
+            event  : event.clone(),
+            author : author.to_owned(),
+            title  : title.to_owned(),
+            content: content.to_owned(),
+        };
+
+        notifications::notify_preferred(db_pool, channels, *user_id, message).await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize, Validate)]
+pub struct MentionsQuery
+{
+    #[validate(range(min = 1, message = "must be at least 1"))]
+    pub page    : Option<i64>,
+    #[validate(range(min = 1, max = 100, message = "must be between 1 and 100"))]
+    pub per_page: Option<i64>,
+}
+
+#[derive(FromRow)]
+struct MentionRow
+{
+    comment_id        : i64,
+    event_id          : i64,
+    event_name        : String,
+    title             : String,
+    content           : String,
+    created_at        : DateTime<Utc>,
+    author_id         : i64,
+    author_external_id: Option<String>,
+    author_name       : String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Mention
+{
+    pub comment_id: i64,
+    pub event_id  : i64,
+    pub event_name: String,
+    pub author    : User,
+    pub title     : String,
+    pub content   : String,
+    /// When the user was first mentioned in the comment.
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<MentionRow> for Mention
+{
+    fn from(row: MentionRow) -> Self
+    {
+        Mention {
+            comment_id: row.comment_id,
+            event_id  : row.event_id,
+            event_name: row.event_name,
+            author    : User { id: row.author_id, external_id: row.author_external_id, name: row.author_name },
+            title     : row.title,
+            content   : row.content,
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// Latest mentions of a user first, in the events the current user can see.
+pub async fn list_user_mentions(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path(user_id): Path<i64>,
+    ValidQuery(query): ValidQuery<MentionsQuery>,
+) -> Result<(StatusCode, Json<Vec<Mention>>), ApiError>
+{
+    let page     = query.page.unwrap_or(1);
+    let per_page = query.per_page.unwrap_or(DEFAULT_MENTIONS_PER_PAGE);
+
+    let mentions = sqlx::query_as::<_, MentionRow>
+        ("SELECT events_comments.id AS comment_id, events.id AS event_id, events.name AS event_name,
+            events_comments.title, events_comments.content, events_comments_mentions.created_at,
+            users.id AS author_id, users.external_id AS author_external_id, users.name AS author_name
+        FROM events_comments_mentions
+        JOIN events_comments ON events_comments.id = events_comments_mentions.comment_id
+        JOIN events ON events.id = events_comments.event_id
+        JOIN users ON users.id = events_comments.user_id
+        WHERE events_comments_mentions.user_id = $1 AND events_comments.deleted_at IS NULL
+            AND (NOT events.private OR EXISTS (
+                SELECT 1 FROM users_events WHERE users_events.event_id = events.id AND users_events.user_id = $2
+            ))
+        ORDER BY events_comments_mentions.created_at DESC, events_comments.id DESC
+        LIMIT $3 OFFSET $4")
+        .bind(user_id)
+        .bind(user.id)
+        .bind(per_page)
+        .bind((page - 1) * per_page)
+        .fetch_all(&db_pool)
+        .await?
+        .into_iter()
+        .map(Mention::from)
+        .collect();
+
+    Ok((StatusCode::OK, Json(mentions)))
+}
@@ -1,4 +1,13 @@
 pub mod user;
 pub mod tag;
 pub mod event;
-pub mod contact;
\ No newline at end of file
+pub mod contact;
+pub mod calendar;
+pub mod availability;
+pub mod participation;
+pub mod invitation;
+pub mod waitlist;
+pub mod comment;
+pub mod mention;
+pub mod reaction;
+pub mod attachment;
\ No newline at end of file
@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+use axum::{extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use serde_json::{json, Value};
+use chrono::{DateTime, Utc};
+use validator::Validate;
+use crate::{
+    handlers::{event, waitlist::{self, Places}},
+    helpers::{
+        auth::CurrentUser,
+        error::ApiError,
+        extract::{Json, Path, ValidJson},
+        policy::{self, Action},
+        recurrence::{self, Schedule},
+        time_zone::DateTimeInput,
+    },
+    notifications::Channels,
+};
+
+/// Answer of a participant to an event, like the iCalendar PARTSTAT.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, sqlx::Type, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "participation_status", rename_all = "snake_case")]
+pub enum ParticipationStatus
+{
+    NeedsAction,
+    Accepted,
+    Declined,
+    Tentative,
+}
+
+impl ParticipationStatus
+{
+    pub fn part_stat(&self) -> &'static str
+    {
+        match self
+        {
+            ParticipationStatus::NeedsAction => "NEEDS-ACTION",
+            ParticipationStatus::Accepted    => "ACCEPTED",
+            ParticipationStatus::Declined    => "DECLINED",
+            ParticipationStatus::Tentative   => "TENTATIVE",
+        }
+    }
+}
+
+/// How many participants answered each status.
+#[derive(Debug, Default, Serialize)]
+pub struct Attendance
+{
+    pub needs_action: i64,
+    pub accepted    : i64,
+    pub declined    : i64,
+    pub tentative   : i64,
+}
+
+impl FromIterator<ParticipationStatus> for Attendance
+{
+    fn from_iter<I: IntoIterator<Item = ParticipationStatus>>(statuses: I) -> Self
+    {
+        let mut attendance = Attendance::default();
+
+        for status in statuses
+        {
+            match status
+            {
+                ParticipationStatus::NeedsAction => attendance.needs_action += 1,
+                ParticipationStatus::Accepted    => attendance.accepted += 1,
+                ParticipationStatus::Declined    => attendance.declined += 1,
+                ParticipationStatus::Tentative   => attendance.tentative += 1,
+            }
+        }
+
+        attendance
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct RsvpRequest
+{
+    pub status : ParticipationStatus,
+    #[validate(length(max = 255, message = "must have at most 255 characters"))]
+    pub comment: Option<String>,
+}
+
+/// Participants answer for themselves only.
+pub async fn respond_to_event(
+    State(db_pool): State<PgPool>,
+    State(channels): State<Channels>,
+    CurrentUser(user): CurrentUser,
+    Path((event_id, user_id)): Path<(i64, i64)>,
+    ValidJson(req): ValidJson<RsvpRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    let access = policy::authorize_event(&db_pool, user.id, event_id, Action::Respond).await?;
+
+    if user_id != user.id
+    {
+        return Err(ApiError::Forbidden { action: Action::Respond, role: access.role });
+    }
+
+    let mut transaction = db_pool.begin().await?;
+
+    let places = Places::lock(&mut transaction, event_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Event not found.".into()))?;
+
+    let previous = sqlx::query!(
+        r#"SELECT status AS "status: ParticipationStatus", owner
+        FROM users_events WHERE user_id = $1 AND event_id = $2"#,
+        user_id,
+        event_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+
+    if let Some(previous) = previous
+    {
+        if previous.status == ParticipationStatus::Declined && req.status != ParticipationStatus::Declined
+            && !previous.owner && places.full()
+        {
+            return Err(ApiError::Conflict("The event is full.".into()));
+        }
+    }
+
+    sqlx::query!(
+        "UPDATE users_events SET status = $1, comment = $2, responded_at = NOW()
+        WHERE user_id = $3 AND event_id = $4",
+        req.status as ParticipationStatus,
+        req.comment,
+        user_id,
+        event_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    let promoted = waitlist::promote(&mut transaction, event_id).await?;
+
+    transaction.commit().await?;
+
+    waitlist::notify_promoted(&db_pool, &channels, event_id, &promoted).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"message": "Response saved successfully."})),
+    ))
+}
+
+/// Identifies an occurrence the way its answers are kept: stored schedules by
+/// id, and occurrences of recurrences by their original start time, even when
+/// edited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OccurrenceKey
+{
+    Schedule(i64),
+    Recurrence(i64, DateTime<Utc>),
+}
+
+impl OccurrenceKey
+{
+    /// From the columns of a `schedules` row.
+    fn new(schedule_id: i64, recurrence_id: Option<i64>, original_start_time: Option<DateTime<Utc>>) -> Self
+    {
+        match (recurrence_id, original_start_time)
+        {
+            (Some(recurrence_id), Some(original_start_time)) => OccurrenceKey::Recurrence(recurrence_id, original_start_time),
+            _ => OccurrenceKey::Schedule(schedule_id),
+        }
+    }
+}
+
+impl From<&Schedule> for OccurrenceKey
+{
+    fn from(schedule: &Schedule) -> Self
+    {
+        OccurrenceKey::new(
+            schedule.id.unwrap_or_default(),
+            schedule.recurrence_id,
+            schedule.original_start_time.map(|original_start_time| original_start_time.to_utc()),
+        )
+    }
+}
+
+/// What some users answered to the events they take part in and to single
+/// occurrences of them.
+pub struct Responses
+{
+    /// Users of each event, with their status in it.
+    events     : HashMap<i64, Vec<(i64, ParticipationStatus)>>,
+    occurrences: HashMap<(i64, OccurrenceKey), ParticipationStatus>,
+}
+
+impl Responses
+{
+    pub async fn load(connection: &mut PgConnection, user_ids: &[i64]) -> Result<Self, sqlx::Error>
+    {
+        let mut events: HashMap<i64, Vec<(i64, ParticipationStatus)>> = HashMap::new();
+
+        for participation in sqlx::query!(
+            r#"SELECT user_id, event_id, status AS "status: ParticipationStatus"
+            FROM users_events WHERE user_id = ANY($1)"#,
+            user_ids
+        )
+        .fetch_all(&mut *connection)
+        .await?
+        {
+            events.entry(participation.event_id).or_default().push((participation.user_id, participation.status));
+        }
+
+        let occurrences = sqlx::query!(
+            r#"SELECT user_id, schedule_id, recurrence_id, original_start_time,
+                status AS "status: ParticipationStatus"
+            FROM occurrence_responses WHERE user_id = ANY($1)"#,
+            user_ids
+        )
+        .fetch_all(&mut *connection)
+        .await?
+        .into_iter()
+        .map(|response| {
+            let key = OccurrenceKey::new(
+                response.schedule_id.unwrap_or_default(),
+                response.recurrence_id,
+                response.original_start_time,
+            );
+
+            ((response.user_id, key), response.status)
+        })
+        .collect();
+
+        Ok(Responses { events, occurrences })
+    }
+
+    /// Sorted ids of the events the users take part in.
+    pub fn event_ids(&self) -> Vec<i64>
+    {
+        let mut event_ids: Vec<i64> = self.events.keys().copied().collect();
+        event_ids.sort_unstable();
+        event_ids
+    }
+
+    /// The users taking part in the event of a schedule, with their answer to
+    /// it, or else to the whole event.
+    pub fn statuses<'a>(&'a self, schedule: &Schedule) -> impl Iterator<Item = (i64, ParticipationStatus)> + 'a
+    {
+        let key = OccurrenceKey::from(schedule);
+
+        self.events.get(&schedule.event_id)
+            .into_iter()
+            .flatten()
+            .map(move |(user_id, status)| {
+                (*user_id, self.occurrences.get(&(*user_id, key)).copied().unwrap_or(*status))
+            })
+    }
+}
+
+async fn save_occurrence_response(
+    db_pool: &PgPool,
+    user_id: i64,
+    event_id: i64,
+    key: OccurrenceKey,
+    req: RsvpRequest,
+) -> Result<(), sqlx::Error>
+{
+    match key
+    {
+        OccurrenceKey::Schedule(schedule_id) => sqlx::query!(
+            "INSERT INTO occurrence_responses (user_id, event_id, schedule_id, status, comment)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (user_id, schedule_id)
+            DO UPDATE SET status = EXCLUDED.status, comment = EXCLUDED.comment, responded_at = NOW()",
+            user_id,
+            event_id,
+            schedule_id,
+            req.status as ParticipationStatus,
+            req.comment
+        )
+        .execute(db_pool)
+        .await?,
+        OccurrenceKey::Recurrence(recurrence_id, original_start_time) => sqlx::query!(
+            "INSERT INTO occurrence_responses (user_id, event_id, recurrence_id, original_start_time, status, comment)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (user_id, recurrence_id, original_start_time)
+            DO UPDATE SET status = EXCLUDED.status, comment = EXCLUDED.comment, responded_at = NOW()",
+            user_id,
+            event_id,
+            recurrence_id,
+            original_start_time,
+            req.status as ParticipationStatus,
+            req.comment
+        )
+        .execute(db_pool)
+        .await?,
+    };
+
+    Ok(())
+}
+
+/// Goes back to the answer to the whole event.
+async fn delete_occurrence_response(db_pool: &PgPool, user_id: i64, key: OccurrenceKey) -> Result<(), sqlx::Error>
+{
+    let (schedule_id, recurrence_id, original_start_time) = match key
+    {
+        OccurrenceKey::Schedule(schedule_id) => (Some(schedule_id), None, None),
+        OccurrenceKey::Recurrence(recurrence_id, original_start_time) =>
+            (None, Some(recurrence_id), Some(original_start_time)),
+    };
+
+    sqlx::query!(
+        "DELETE FROM occurrence_responses
+        WHERE user_id = $1 AND (schedule_id = $2 OR (recurrence_id = $3 AND original_start_time = $4))",
+        user_id,
+        schedule_id,
+        recurrence_id,
+        original_start_time
+    )
+    .execute(db_pool)
+    .await?;
+
+    Ok(())
+}
+
+/// A stored schedule, keyed like its recurrence when it is an edited occurrence.
+async fn find_schedule(db_pool: &PgPool, user_id: i64, id: i64) -> Result<(i64, OccurrenceKey), ApiError>
+{
+    let schedule = sqlx::query!(
+        "SELECT event_id, recurrence_id, original_start_time FROM schedules WHERE id = $1",
+        id
+    )
+    .fetch_optional(db_pool)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Schedule not found.".into()))?;
+
+    policy::authorize_event(db_pool, user_id, schedule.event_id, Action::Respond).await?;
+
+    Ok((schedule.event_id, OccurrenceKey::new(id, schedule.recurrence_id, schedule.original_start_time)))
+}
+
+/// An occurrence of a recurrence, identified by its original start time.
+async fn find_occurrence(
+    db_pool: &PgPool,
+    user_id: i64,
+    id: i64,
+    occurrence: DateTimeInput,
+) -> Result<(i64, OccurrenceKey), ApiError>
+{
+    let recurrence = recurrence::find_recurrence(db_pool, id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Recurrence not found.".into()))?;
+
+    policy::authorize_event(db_pool, user_id, recurrence.event_id, Action::Respond).await?;
+
+    let occurrence = event::resolve_time(occurrence, &recurrence.time_zone())?;
+
+    if !recurrence.has_occurrence(occurrence)?
+    {
+        return Err(event::occurrence_not_found());
+    }
+
+    Ok((recurrence.event_id, OccurrenceKey::Recurrence(id, occurrence)))
+}
+
+/// Answers a single schedule, keeping the answer to the rest of the event.
+pub async fn respond_to_schedule(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+    ValidJson(req): ValidJson<RsvpRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    let (event_id, key) = find_schedule(&db_pool, user.id, id).await?;
+
+    save_occurrence_response(&db_pool, user.id, event_id, key, req).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"message": "Response saved successfully."})),
+    ))
+}
+
+pub async fn clear_schedule_response(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    let (_, key) = find_schedule(&db_pool, user.id, id).await?;
+
+    delete_occurrence_response(&db_pool, user.id, key).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"message": "Response cleared successfully."})),
+    ))
+}
+
+/// Answers a single occurrence of a recurrence, keeping the answer to the rest
+/// of the event.
+pub async fn respond_to_occurrence(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path((id, occurrence)): Path<(i64, DateTimeInput)>,
+    ValidJson(req): ValidJson<RsvpRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    let (event_id, key) = find_occurrence(&db_pool, user.id, id, occurrence).await?;
+
+    save_occurrence_response(&db_pool, user.id, event_id, key, req).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"message": "Response saved successfully."})),
+    ))
+}
+
+pub async fn clear_occurrence_response(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path((id, occurrence)): Path<(i64, DateTimeInput)>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    let (_, key) = find_occurrence(&db_pool, user.id, id, occurrence).await?;
+
+    delete_occurrence_response(&db_pool, user.id, key).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"message": "Response cleared successfully."})),
+    ))
+}
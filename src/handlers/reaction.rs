@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use axum::{extract::State, http::StatusCode};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use serde_json::{json, Value};
+use crate::helpers::{
+    auth::CurrentUser,
+    error::ApiError,
+    extract::{Json, Path},
+    policy::{self, Action},
+    validation,
+};
+
+/// Emoji, or short names like `+1` or `tada`, of up to 32 characters.
+fn check_emoji(emoji: &str) -> Result<(), ApiError>
+{
+    let valid = (1..=32).contains(&emoji.chars().count())
+        && emoji.chars().all(|c| match c.is_ascii()
+        {
+            true  => c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-'),
+            false => !c.is_whitespace() && !c.is_control(),
+        });
+
+    match valid
+    {
+        true  => Ok(()),
+        false => Err(ApiError::Validation(validation::field_error(
+            "emoji",
+            validation::error("emoji", "must be an emoji or a short name like +1"),
+        ))),
+    }
+}
+
+/// Reactions of everyone to a comment with one emoji.
+#[derive(Debug, FromRow, Serialize)]
+pub struct Reaction
+{
+    #[serde(skip)]
+    pub comment_id   : i64,
+    pub emoji        : String,
+    pub count        : i64,
+    pub reacted_by_me: bool,
+}
+
+/// Reactions to each of the comments, the first given emoji first.
+pub async fn list_reactions(
+    db_pool: &PgPool,
+    comment_ids: &[i64],
+    current_user_id: i64,
+) -> Result<HashMap<i64, Vec<Reaction>>, sqlx::Error>
+{
+    let reactions = sqlx::query_as::<_, Reaction>
+        ("SELECT comment_id, emoji, COUNT(*) AS count, BOOL_OR(user_id = $2) AS reacted_by_me
+        FROM events_comments_reactions
+        WHERE comment_id = ANY($1)
+        GROUP BY comment_id, emoji
+        ORDER BY comment_id, MIN(created_at), emoji")
+        .bind(comment_ids)
+        .bind(current_user_id)
+        .fetch_all(db_pool)
+        .await?;
+
+    let mut by_comment: HashMap<i64, Vec<Reaction>> = HashMap::new();
+
+    for reaction in reactions
+    {
+        by_comment.entry(reaction.comment_id).or_default().push(reaction);
+    }
+
+    Ok(by_comment)
+}
+
+/// Reacting twice with the same emoji changes nothing.
+pub async fn add_reaction(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path((id, emoji)): Path<(i64, String)>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    check_emoji(&emoji)?;
+
+    policy::authorize_comment(&db_pool, user.id, id, Action::Comment).await?;
+
+    sqlx::query!(
+        "INSERT INTO events_comments_reactions (comment_id, user_id, emoji) VALUES ($1, $2, $3)
+        ON CONFLICT (comment_id, user_id, emoji) DO NOTHING",
+        id,
+        user.id,
+        emoji
+    )
+    .execute(&db_pool)
+    .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"message": "Reaction added successfully."})),
+    ))
+}
+
+pub async fn remove_reaction(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path((id, emoji)): Path<(i64, String)>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
+{
+    policy::authorize_comment(&db_pool, user.id, id, Action::View).await?;
+
+    let result = sqlx::query!(
+        "DELETE FROM events_comments_reactions WHERE comment_id = $1 AND user_id = $2 AND emoji = $3",
+        id,
+        user.id,
+        emoji
+    )
+    .execute(&db_pool)
+    .await?;
+
+    match result.rows_affected()
+    {
+        0 => Err(ApiError::NotFound("Reaction not found.".into())),
+        _ => Ok((
+            StatusCode::OK,
+            Json(json!({"message": "Reaction removed successfully."})),
+        )),
+    }
+}
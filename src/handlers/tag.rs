@@ -1,8 +1,9 @@
-use axum::{extract::{Path, State}, http::StatusCode, Json,};
+use axum::{extract::State, http::StatusCode};
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, PgPool};
 use serde_json::{json, Value};
-use crate::helpers::error::database_err_mapper;
+use validator::Validate;
+use crate::helpers::{error::ApiError, extract::{Json, Path, ValidJson}, validation::COLOR_REGEX};
 
 #[derive(Debug, FromRow, Serialize)]
 pub struct Tag 
@@ -14,26 +15,27 @@ pub struct Tag
 
 pub async fn list_tags(
     State(db_pool): State<PgPool>,
-) -> Result<(StatusCode, Json<Vec<Tag>>), (StatusCode, Json<Value>)>
+) -> Result<(StatusCode, Json<Vec<Tag>>), ApiError>
 {
     let tags = sqlx::query_as::<_, Tag>("SELECT * FROM tags")
         .fetch_all(&db_pool)
-        .await
-        .map_err(database_err_mapper)?;
+        .await?;
 
     Ok((StatusCode::OK, Json(tags)))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 pub struct CreateOrUpdateTagRequest {
+    #[validate(length(min = 1, max = 255, message = "must have between 1 and 255 characters"))]
     name: String,
+    #[validate(regex(path = *COLOR_REGEX, message = "must be 6 uppercase hexadecimal digits, like 9E9E9E"))]
     color: String,
 }
 
 pub async fn create_tag(
     State(db_pool): State<PgPool>,
-    Json(req): Json<CreateOrUpdateTagRequest>,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+    ValidJson(req): ValidJson<CreateOrUpdateTagRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
 {
     let result = sqlx::query!(
         "INSERT INTO tags (name, color) VALUES ($1, $2) RETURNING id",
@@ -41,8 +43,7 @@ pub async fn create_tag(
         req.color
     )
     .fetch_one(&db_pool)
-    .await
-    .map_err(database_err_mapper)?;
+    .await?;
 
     Ok((
         StatusCode::CREATED,
@@ -56,8 +57,8 @@ pub async fn create_tag(
 pub async fn update_tag(
     State(db_pool): State<PgPool>,
     Path(id): Path<i64>,
-    Json(req): Json<CreateOrUpdateTagRequest>,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+    ValidJson(req): ValidJson<CreateOrUpdateTagRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
 {
     let result = sqlx::query!(
         "UPDATE tags SET name = $1, color = $2 WHERE id = $3",
@@ -66,15 +67,11 @@ pub async fn update_tag(
         id
     )
     .execute(&db_pool)
-    .await
-    .map_err(database_err_mapper)?;
+    .await?;
 
     match result.rows_affected()
     {
-        0 => Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"message": "Tag not found."})),
-        )),
+        0 => Err(ApiError::NotFound("Tag not found.".into())),
         _ => Ok((
             StatusCode::OK,
             Json(json!({"message": "Tag updated successfully."})),
@@ -85,19 +82,15 @@ pub async fn update_tag(
 pub async fn delete_tag(
     State(db_pool): State<PgPool>,
     Path(id): Path<i64>,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> 
+) -> Result<(StatusCode, Json<Value>), ApiError> 
 {
     let result = sqlx::query!("DELETE FROM tags WHERE id = $1", id)
         .execute(&db_pool)
-        .await
-        .map_err(database_err_mapper)?;
+        .await?;
 
     match result.rows_affected()
     {
-        0 => Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"message": "Tag not found."}))
-        )),
+        0 => Err(ApiError::NotFound("Tag not found.".into())),
         _ => Ok((
             StatusCode::OK,
             Json(json!({"message": "Tag deleted successfully.",}))
@@ -1,8 +1,14 @@
-use axum::{extract::{Path, State}, http::StatusCode, Json,};
+use axum::{extract::State, http::StatusCode};
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, PgPool, Type};
 use serde_json::{json, Value};
-use crate::helpers::error::database_err_mapper;
+use crate::helpers::{
+    auth::{Claims, CurrentUser},
+    error::ApiError,
+    extract::{Json, Path, ValidJson},
+    policy::{self, Action},
+};
+use validator::Validate;
 
 #[derive(Debug, FromRow, Serialize)]
 pub struct User
@@ -14,37 +20,44 @@ pub struct User
 
 pub async fn list_users(
     State(db_pool): State<PgPool>,
-) -> Result<(StatusCode, Json<Vec<User>>), (StatusCode, Json<Value>)>
+) -> Result<(StatusCode, Json<Vec<User>>), ApiError>
 {
     let users = sqlx::query_as::<_, User>
         ("SELECT * FROM users")
         .fetch_all(&db_pool)
-        .await
-        .map_err(database_err_mapper)?;
+        .await?;
 
     Ok((StatusCode::OK, Json(users)))
 }
 
-#[derive(Deserialize)]
+pub async fn get_current_user(
+    CurrentUser(user): CurrentUser,
+) -> Result<(StatusCode, Json<User>), ApiError>
+{
+    Ok((StatusCode::OK, Json(user)))
+}
+
+#[derive(Deserialize, Validate)]
 pub struct CreateOrUpdateUserRequest
 {
-    name       : String,
-    external_id: String,
+    #[validate(length(min = 1, max = 255, message = "must have between 1 and 255 characters"))]
+    name: String,
 }
 
+/// Registers the subject of the bearer token as a user.
 pub async fn create_user(
     State(db_pool): State<PgPool>,
-    Json(req): Json<CreateOrUpdateUserRequest>,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+    claims: Claims,
+    ValidJson(req): ValidJson<CreateOrUpdateUserRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
 {
     let result = sqlx::query!(
         "INSERT INTO users (name, external_id) VALUES ($1, $2) RETURNING id",
         req.name,
-        req.external_id
+        claims.sub
     )
     .fetch_one(&db_pool)
-    .await
-    .map_err(database_err_mapper)?;
+    .await?;
 
     Ok((
         StatusCode::CREATED,
@@ -57,26 +70,21 @@ pub async fn create_user(
 
 pub async fn update_user(
     State(db_pool): State<PgPool>,
-    Path(id): Path<i64>,
-    Json(req): Json<CreateOrUpdateUserRequest>,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+    CurrentUser(user): CurrentUser,
+    ValidJson(req): ValidJson<CreateOrUpdateUserRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
 {
     let result = sqlx::query!(
-        "UPDATE users SET name = $1, external_id = $2 WHERE id = $3",
+        "UPDATE users SET name = $1 WHERE id = $2",
         req.name,
-        req.external_id,
-        id
+        user.id
     )
     .execute(&db_pool)
-    .await
-    .map_err(database_err_mapper)?;
+    .await?;
 
     match result.rows_affected()
     {
-        0 => Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"message": "User not found."})),
-        )),
+        0 => Err(ApiError::NotFound("User not found.".into())),
         _ => Ok((
             StatusCode::OK,
             Json(json!({"message": "User updated successfully.",})),
@@ -86,29 +94,26 @@ pub async fn update_user(
 
 pub async fn delete_user(
     State(db_pool): State<PgPool>,
-    Path(id): Path<i64>,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+    CurrentUser(user): CurrentUser,
+) -> Result<(StatusCode, Json<Value>), ApiError>
 {
-    let result = sqlx::query!("DELETE FROM users WHERE id = $1", id)
+    let result = sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
         .execute(&db_pool)
-        .await
-        .map_err(database_err_mapper)?;
+        .await?;
 
     match result.rows_affected()
     {
-        0 => Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"message": "User not found."}))
-        )),
+        0 => Err(ApiError::NotFound("User not found.".into())),
         _ => Ok((StatusCode::OK,
             Json(json!({"message": "User deleted successfully."}))
         ))
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Type)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
 #[serde(rename_all = "lowercase")]
 #[sqlx(type_name = "reminder_type", rename_all = "lowercase")]
+#[allow(clippy::upper_case_acronyms)]
 pub enum ReminderType
 {
     Email,
@@ -130,50 +135,52 @@ pub struct Reminder
 
 pub async fn list_reminders_from_user(
     State(db_pool): State<PgPool>,
-    Path(user_id): Path<i64>,
-) -> Result<(StatusCode, Json<Vec<Reminder>>), (StatusCode, Json<Value>)>
+    CurrentUser(user): CurrentUser,
+) -> Result<(StatusCode, Json<Vec<Reminder>>), ApiError>
 {
     let reminders = sqlx::query_as::<_, Reminder>
-        ("SELECT * FROM reminders WHERE user_contact_id = $1")
-        .bind(user_id)
+        ("SELECT reminders.* FROM reminders
+        JOIN users_contacts ON users_contacts.id = reminders.user_contact_id
+        WHERE users_contacts.user_id = $1")
+        .bind(user.id)
         .fetch_all(&db_pool)
-        .await
-        .map_err(database_err_mapper)?;
+        .await?;
 
     Ok((StatusCode::OK, Json(reminders)))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 pub struct AddReminderRequest
 {
     r#type        : ReminderType,
+    #[validate(range(min = 0, message = "must not be negative"))]
     minutes_before: i32
 }
 
 pub async fn add_reminder(
     State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
     Path((user_contact_id, event_id)): Path<(i64, i64)>,
-    Json(req): Json<AddReminderRequest>,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+    ValidJson(req): ValidJson<AddReminderRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError>
 {
+    policy::authorize_event(&db_pool, user.id, event_id, Action::View).await?;
+
     let result = sqlx::query!(
         "INSERT INTO reminders (event_id, user_contact_id, type, minutes_before)
-        VALUES ($1, $2, $3, $4)",
+        SELECT $1, id, $3, $4 FROM users_contacts WHERE id = $2 AND user_id = $5",
         event_id,
         user_contact_id,
         req.r#type as ReminderType,
-        req.minutes_before
+        req.minutes_before,
+        user.id
     )
     .execute(&db_pool)
-    .await
-    .map_err(database_err_mapper)?;
+    .await?;
 
     match result.rows_affected()
     {
-        0 => Ok((
-            StatusCode::NOT_FOUND,
-            Json(json!({"message": "Event or user contact not found."})),
-        )),
+        0 => Err(ApiError::NotFound("User contact not found.".into())),
         _ => Ok((
             StatusCode::OK,
             Json(json!({"message": "Reminder added to event successfully."})),
@@ -183,24 +190,24 @@ pub async fn add_reminder(
 
 pub async fn remove_reminder(
     State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
     Path((user_contact_id, event_id)): Path<(i64, i64)>,
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>
+) -> Result<(StatusCode, Json<Value>), ApiError>
 {
     let result = sqlx::query!(
-        "DELETE FROM reminders WHERE event_id = $1 AND user_contact_id = $2",
+        "DELETE FROM reminders USING users_contacts
+        WHERE users_contacts.id = reminders.user_contact_id
+            AND reminders.event_id = $1 AND reminders.user_contact_id = $2 AND users_contacts.user_id = $3",
         event_id,
-        user_contact_id
+        user_contact_id,
+        user.id
     )
     .execute(&db_pool)
-    .await
-    .map_err(database_err_mapper)?;
+    .await?;
 
     match result.rows_affected()
     {
-        0 => Ok((
-            StatusCode::NOT_FOUND,
-            Json(json!({"message": "Reminder not found."})),
-        )),
+        0 => Err(ApiError::NotFound("Reminder not found.".into())),
         _ => Ok((
             StatusCode::OK,
             Json(json!({"message": "Reminder removed successfully."})),
@@ -0,0 +1,181 @@
+use axum::{extract::State, http::StatusCode};
+use serde::Serialize;
+use sqlx::{FromRow, PgConnection, PgPool};
+use chrono::{DateTime, Utc};
+use crate::{
+    handlers::participation::ParticipationStatus,
+    helpers::{
+        auth::CurrentUser,
+        error::ApiError,
+        extract::{Json, Path},
+        policy::{self, Action},
+    },
+    notifications::{self, Channels, Message},
+};
+
+/// Places of an event, taken by the participants that didn't decline.
+pub struct Places
+{
+    pub capacity: Option<i32>,
+    pub taken   : i64,
+}
+
+impl Places
+{
+    /// Locks the event until the end of the transaction, so places are given
+    /// one at a time.
+    pub async fn lock(connection: &mut PgConnection, event_id: i64) -> Result<Option<Self>, sqlx::Error>
+    {
+        let places = sqlx::query!(
+            r#"SELECT capacity,
+                (SELECT COUNT(*) FROM users_events WHERE event_id = events.id AND status <> 'declined') AS "taken!"
+            FROM events WHERE id = $1
+            FOR UPDATE"#,
+            event_id
+        )
+        .fetch_optional(connection)
+        .await?;
+
+        Ok(places.map(|places| Places { capacity: places.capacity, taken: places.taken }))
+    }
+
+    pub fn full(&self) -> bool
+    {
+        self.capacity.is_some_and(|capacity| self.taken >= i64::from(capacity))
+    }
+
+    /// How many places are left, or `None` without a capacity.
+    pub fn free(&self) -> Option<i64>
+    {
+        self.capacity.map(|capacity| (i64::from(capacity) - self.taken).max(0))
+    }
+}
+
+/// Puts a user at the end of the waitlist of an event, returning their position.
+pub async fn enqueue(
+    connection: &mut PgConnection,
+    event_id: i64,
+    user_id: i64,
+    status: ParticipationStatus,
+) -> Result<i64, ApiError>
+{
+    let participant = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM users_events WHERE user_id = $1 AND event_id = $2) AS "exists!""#,
+        user_id,
+        event_id
+    )
+    .fetch_one(&mut *connection)
+    .await?;
+
+    if participant
+    {
+        return Err(ApiError::Conflict("User already takes part in the event.".into()));
+    }
+
+    sqlx::query!(
+        "INSERT INTO waitlist (event_id, user_id, status) VALUES ($1, $2, $3)",
+        event_id,
+        user_id,
+        status as ParticipationStatus
+    )
+    .execute(&mut *connection)
+    .await?;
+
+    let position = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "position!" FROM waitlist WHERE event_id = $1"#,
+        event_id
+    )
+    .fetch_one(connection)
+    .await?;
+
+    Ok(position)
+}
+
+/// Moves the first users of the waitlist into the free places of an event,
+/// all of them when it has no capacity, returning who was promoted.
+pub async fn promote(connection: &mut PgConnection, event_id: i64) -> Result<Vec<i64>, sqlx::Error>
+{
+    let Some(places) = Places::lock(&mut *connection, event_id).await? else
+    {
+        return Ok(Vec::new());
+    };
+
+    if places.free() == Some(0)
+    {
+        return Ok(Vec::new());
+    }
+
+    sqlx::query_scalar!(
+        "WITH promoted AS (
+            DELETE FROM waitlist WHERE id IN (
+                SELECT id FROM waitlist WHERE event_id = $1 ORDER BY id LIMIT $2
+            )
+            RETURNING user_id, status
+        )
+        INSERT INTO users_events (user_id, event_id, status, responded_at)
+        SELECT user_id, $1, status, CASE WHEN status <> 'needs_action' THEN NOW() END FROM promoted
+        RETURNING user_id",
+        event_id,
+        places.free()
+    )
+    .fetch_all(connection)
+    .await
+}
+
+/// Tells the promoted users they take part in the event, once the promotion
+/// is committed.
+pub async fn notify_promoted(
+    db_pool: &PgPool,
+    channels: &Channels,
+    event_id: i64,
+    user_ids: &[i64],
+) -> Result<(), sqlx::Error>
+{
+    if user_ids.is_empty()
+    {
+        return Ok(());
+    }
+
+    let event = notifications::notified_event(db_pool, event_id).await?;
+
+    for user_id in user_ids
+    {
+        notifications::notify_user(db_pool, channels, *user_id, Message::Promotion { event: event.clone() }).await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct Waitlisted
+{
+    pub position   : i64,
+    pub id         : i64,
+    pub external_id: Option<String>,
+    pub name       : String,
+    pub status     : ParticipationStatus,
+    pub created_at : DateTime<Utc>,
+}
+
+/// The waitlist is as visible as the participants.
+pub async fn list_waitlist(
+    State(db_pool): State<PgPool>,
+    CurrentUser(user): CurrentUser,
+    Path(event_id): Path<i64>,
+) -> Result<(StatusCode, Json<Vec<Waitlisted>>), ApiError>
+{
+    policy::authorize_event(&db_pool, user.id, event_id, Action::View).await?;
+
+    let waitlist = sqlx::query_as::<_, Waitlisted>
+        ("SELECT ROW_NUMBER() OVER (ORDER BY waitlist.id) AS position,
+            users.id, users.external_id, users.name, waitlist.status, waitlist.created_at
+        FROM waitlist
+        JOIN users ON users.id = waitlist.user_id
+        WHERE waitlist.event_id = $1
+        ORDER BY waitlist.id")
+        .bind(event_id)
+        .fetch_all(&db_pool)
+        .await?;
+
+    Ok((StatusCode::OK, Json(waitlist)))
+}
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Query},
+    http::{header, request::Parts},
+};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use crate::{handlers::user::User, helpers::error::ApiError};
+
+/// Key and rules the bearer tokens are validated with.
+#[derive(Clone)]
+pub struct AuthKeys
+{
+    decoding_key: Arc<DecodingKey>,
+    validation  : Arc<Validation>,
+}
+
+impl AuthKeys
+{
+    /// Reads the HS256 `JWT_SECRET`, and the optional `JWT_ISSUER` and
+    /// `JWT_AUDIENCE` tokens must have been issued by and for.
+    pub fn from_env() -> Self
+    {
+        let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET not found in env file");
+
+        let mut validation = Validation::new(Algorithm::HS256);
+
+        if let Ok(issuer) = std::env::var("JWT_ISSUER")
+        {
+            validation.set_issuer(&[issuer]);
+        }
+
+        match std::env::var("JWT_AUDIENCE")
+        {
+            Ok(audience) => validation.set_audience(&[audience]),
+            Err(_) => validation.validate_aud = false,
+        }
+
+        AuthKeys {
+            decoding_key: Arc::new(DecodingKey::from_secret(secret.as_bytes())),
+            validation  : Arc::new(validation),
+        }
+    }
+}
+
+/// Claims of a validated bearer token, whose subject is the `external_id` of
+/// a user, as given by the identity provider.
+#[derive(Debug, Deserialize)]
+pub struct Claims
+{
+    pub sub: String,
+}
+
+#[derive(Deserialize)]
+struct AccessTokenQuery
+{
+    access_token: Option<String>,
+}
+
+/// Takes the token from the `Authorization: Bearer` header or, for clients
+/// that can't send headers like calendar subscriptions, the `access_token`
+/// query parameter.
+#[async_trait]
+impl<S> FromRequestParts<S> for Claims
+where
+    AuthKeys: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection>
+    {
+        let keys = AuthKeys::from_ref(state);
+
+        let header_token = parts.headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::to_owned);
+
+        let token = match header_token
+        {
+            Some(token) => token,
+            None => Query::<AccessTokenQuery>::try_from_uri(&parts.uri)
+                .ok()
+                .and_then(|Query(query)| query.access_token)
+                .ok_or_else(|| ApiError::Unauthorized("Missing bearer token.".into()))?,
+        };
+
+        jsonwebtoken::decode::<Claims>(&token, &keys.decoding_key, &keys.validation)
+            .map(|token_data| token_data.claims)
+            .map_err(|e| ApiError::Unauthorized(format!("Invalid bearer token: {e}")))
+    }
+}
+
+/// The registered user a request is made by.
+pub struct CurrentUser(pub User);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CurrentUser
+where
+    AuthKeys: FromRef<S>,
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection>
+    {
+        let claims = Claims::from_request_parts(parts, state).await?;
+
+        sqlx::query_as::<_, User>("SELECT * FROM users WHERE external_id = $1")
+            .bind(&claims.sub)
+            .fetch_optional(&PgPool::from_ref(state))
+            .await?
+            .map(CurrentUser)
+            .ok_or_else(|| ApiError::Unauthorized("User not registered.".into()))
+    }
+}
+
+/// Audience of invitation tokens, to tell them from any other.
+const INVITATION_AUDIENCE: &str = "invitation";
+
+#[derive(Serialize, Deserialize)]
+struct InvitationClaims
+{
+    /// Id of the invitation.
+    sub: String,
+    aud: String,
+    exp: i64,
+}
+
+/// Signs the tokens invitees answer invitations with, without signing in, and
+/// builds the links sent to them.
+#[derive(Clone)]
+pub struct InvitationTokens
+{
+    encoding_key: Arc<EncodingKey>,
+    decoding_key: Arc<DecodingKey>,
+    validation  : Arc<Validation>,
+    base_url    : Arc<str>,
+}
+
+impl InvitationTokens
+{
+    /// Reads `INVITATION_SECRET`, derived from `JWT_SECRET` when missing so
+    /// invitation tokens are never valid bearer tokens, and the `APP_URL`
+    /// links point to.
+    pub fn from_env() -> Self
+    {
+        let secret = std::env::var("INVITATION_SECRET").unwrap_or_else(|_| {
+            let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET not found in env file");
+            format!("{jwt_secret}:invitations")
+        });
+
+        let base_url = std::env::var("APP_URL").unwrap_or_else(|_| {
+            format!("http://127.0.0.1:{}", std::env::var("APP_PORT").unwrap_or("3000".to_owned()))
+        });
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_audience(&[INVITATION_AUDIENCE]);
+
+        InvitationTokens {
+            encoding_key: Arc::new(EncodingKey::from_secret(secret.as_bytes())),
+            decoding_key: Arc::new(DecodingKey::from_secret(secret.as_bytes())),
+            validation  : Arc::new(validation),
+            base_url    : base_url.trim_end_matches('/').into(),
+        }
+    }
+
+    pub fn sign(&self, invitation_id: i64, expires_at: DateTime<Utc>) -> String
+    {
+        let claims = InvitationClaims {
+            sub: invitation_id.to_string(),
+            aud: INVITATION_AUDIENCE.to_owned(),
+            exp: expires_at.timestamp(),
+        };
+
+        jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &self.encoding_key)
+            .expect("HS256 signing doesn't fail")
+    }
+
+    /// The id of the invitation a token was signed for, while not expired.
+    pub fn verify(&self, token: &str) -> Result<i64, ApiError>
+    {
+        jsonwebtoken::decode::<InvitationClaims>(token, &self.decoding_key, &self.validation)
+            .ok()
+            .and_then(|token_data| token_data.claims.sub.parse().ok())
+            .ok_or_else(|| ApiError::Unauthorized("Invalid or expired invitation token.".into()))
+    }
+
+    /// Where to answer an invitation, like `accept` or `decline`.
+    pub fn link(&self, token: &str, answer: &str) -> String
+    {
+        format!("{}/invitations/{token}/{answer}", self.base_url)
+    }
+}
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use crate::{
+    handlers::participation::{ParticipationStatus, Responses},
+    helpers::{policy, recurrence::{self, Schedule}},
+};
+
+/// How far ahead schedules are compared, as occurrences of recurrences may
+/// never end.
+const CONFLICT_HORIZON_DAYS: i64 = 365;
+
+/// Query of the requests that can double-book users.
+#[derive(Deserialize)]
+pub struct ConflictMode
+{
+    /// Rejects the request when there are conflicts, instead of warning.
+    #[serde(default)]
+    pub strict: bool,
+}
+
+/// A schedule, without anything that identifies it when it belongs to an
+/// event the current user can't see.
+#[derive(Debug, Serialize)]
+pub struct ConflictingSchedule
+{
+    pub event_id     : Option<i64>,
+    pub schedule_id  : Option<i64>,
+    pub recurrence_id: Option<i64>,
+    pub start_time   : DateTime<FixedOffset>,
+    pub end_time     : DateTime<FixedOffset>,
+}
+
+impl ConflictingSchedule
+{
+    fn new(schedule: &Schedule, visible: bool) -> Self
+    {
+        ConflictingSchedule {
+            event_id     : visible.then_some(schedule.event_id),
+            schedule_id  : schedule.id.filter(|_| visible),
+            recurrence_id: schedule.recurrence_id.filter(|_| visible),
+            start_time   : schedule.start_time,
+            end_time     : schedule.end_time,
+        }
+    }
+}
+
+/// A schedule of an event overlapping one of another event the user takes
+/// part in.
+#[derive(Debug, Serialize)]
+pub struct Conflict
+{
+    pub user_id    : i64,
+    pub schedule   : ConflictingSchedule,
+    pub conflicting: ConflictingSchedule,
+}
+
+/// Overlaps between the upcoming schedules of an event and the ones of the
+/// other events of the users, but the ones they declined, as seen by the
+/// current user.
+pub async fn find_conflicts(
+    connection: &mut PgConnection,
+    current_user_id: i64,
+    event_id: i64,
+    user_ids: &[i64],
+) -> Result<Vec<Conflict>, sqlx::Error>
+{
+    let from = Utc::now();
+    let to   = from + Duration::days(CONFLICT_HORIZON_DAYS);
+
+    let schedules = recurrence::list_schedules(&mut *connection, &[event_id], from, to).await?;
+
+    if schedules.is_empty()
+    {
+        return Ok(Vec::new());
+    }
+
+    let responses = Responses::load(&mut *connection, user_ids).await?;
+
+    let event_ids: Vec<i64> = responses.event_ids().into_iter().filter(|id| *id != event_id).collect();
+
+    let others      = recurrence::list_schedules(&mut *connection, &event_ids, from, to).await?;
+    let visible_ids = policy::visible_event_ids(&mut *connection, current_user_id, &event_ids).await?;
+
+    let mut conflicts = Vec::new();
+
+    for other in &others
+    {
+        let overlapping: Vec<&Schedule> = schedules.iter()
+            .filter(|schedule| schedule.start_time < other.end_time && other.start_time < schedule.end_time)
+            .collect();
+
+        if overlapping.is_empty()
+        {
+            continue;
+        }
+
+        let visible = visible_ids.binary_search(&other.event_id).is_ok();
+
+        for (user_id, status) in responses.statuses(other)
+        {
+            if status == ParticipationStatus::Declined
+            {
+                continue;
+            }
+
+            conflicts.extend(overlapping.iter().map(|schedule| Conflict {
+                user_id,
+                schedule   : ConflictingSchedule::new(schedule, true),
+                conflicting: ConflictingSchedule::new(other, visible),
+            }));
+        }
+    }
+
+    Ok(conflicts)
+}
@@ -1,10 +1,244 @@
-use axum::{http::StatusCode, Json,};
-use serde_json::{json, Value};
+use std::fmt;
+use axum::{
+    extract::{
+        multipart::{MultipartError, MultipartRejection},
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use sqlx::error::ErrorKind;
+use validator::ValidationErrors;
+use crate::helpers::{conflict::Conflict, policy::{Action, Role}, validation};
 
-pub fn database_err_mapper(e: sqlx::Error) -> (StatusCode, Json<Value>)
+/// Errors of the API, all answered with a `{"code", "message"}` body, where
+/// `code` is stable for clients to match on.
+#[derive(Debug)]
+pub enum ApiError
 {
-    (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        Json(json!({"message": e.to_string()})),
-    )
-}
\ No newline at end of file
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden { action: Action, role: Role },
+    NotFound(String),
+    Conflict(String),
+    PayloadTooLarge(String),
+    /// Schedules that would double-book users, listed in a `conflicts` array.
+    ScheduleConflict(Vec<Conflict>),
+    Unprocessable(String),
+    /// Request fields that break their rules, listed in a `fields` object.
+    Validation(ValidationErrors),
+    /// Details are logged, not sent.
+    Internal,
+}
+
+impl ApiError
+{
+    pub fn status(&self) -> StatusCode
+    {
+        match self
+        {
+            ApiError::BadRequest(_)       => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_)     => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden { .. }    => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_)         => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_)         => StatusCode::CONFLICT,
+            ApiError::PayloadTooLarge(_)  => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::ScheduleConflict(_) => StatusCode::CONFLICT,
+            ApiError::Unprocessable(_)    => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Validation(_)       => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Internal            => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str
+    {
+        match self
+        {
+            ApiError::BadRequest(_)       => "bad_request",
+            ApiError::Unauthorized(_)     => "unauthorized",
+            ApiError::Forbidden { .. }    => "forbidden",
+            ApiError::NotFound(_)         => "not_found",
+            ApiError::Conflict(_)         => "conflict",
+            ApiError::PayloadTooLarge(_)  => "payload_too_large",
+            ApiError::ScheduleConflict(_) => "schedule_conflict",
+            ApiError::Unprocessable(_)    => "unprocessable",
+            ApiError::Validation(_)       => "validation_failed",
+            ApiError::Internal            => "internal",
+        }
+    }
+}
+
+impl fmt::Display for ApiError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            ApiError::BadRequest(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message)
+            | ApiError::PayloadTooLarge(message)
+            | ApiError::Unprocessable(message) => f.write_str(message),
+            ApiError::Forbidden { action, .. } => write!(f, "Not allowed to {}.", action.description()),
+            ApiError::ScheduleConflict(_)      => f.write_str("The schedules conflict with others of the users."),
+            ApiError::Validation(errors)       =>
+            {
+                let fields: Vec<String> = validation::field_errors(errors).into_keys().collect();
+                write!(f, "Invalid fields: {}.", fields.join(", "))
+            },
+            ApiError::Internal                 => f.write_str("Internal server error."),
+        }
+    }
+}
+
+impl IntoResponse for ApiError
+{
+    fn into_response(self) -> Response
+    {
+        let mut body = json!({
+            "code"   : self.code(),
+            "message": self.to_string(),
+        });
+
+        match &self
+        {
+            ApiError::Forbidden { action, role } =>
+            {
+                body["action"] = json!(action);
+                body["role"]   = json!(role);
+            },
+            ApiError::ScheduleConflict(conflicts) => body["conflicts"] = json!(conflicts),
+            ApiError::Validation(errors) => body["fields"] = json!(validation::field_errors(errors)),
+            _ => {},
+        }
+
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+/// What a violated constraint means for the client. Foreign keys give 404 when
+/// the missing row comes from the path, and 422 when it comes from the body.
+fn constraint_error(constraint: &str) -> Option<ApiError>
+{
+    let error = match constraint
+    {
+        "users_external_id_key"              => ApiError::Conflict("User already registered.".into()),
+        "tags_name_color_key"                => ApiError::Conflict("A tag with this name and color already exists.".into()),
+        "tags_color_check"                   => ApiError::Unprocessable("The color must be 6 uppercase hexadecimal digits.".into()),
+        "users_contacts_user_id_contact_key" => ApiError::Conflict("Contact already registered.".into()),
+        "users_events_pkey"                  => ApiError::Conflict("User already takes part in the event.".into()),
+        "users_events_user_id_fkey"
+        | "waitlist_user_id_fkey"            => ApiError::NotFound("User not found.".into()),
+        "waitlist_event_id_user_id_key"      => ApiError::Conflict("User already waits for a place in the event.".into()),
+        "users_events_event_id_fkey"
+        | "events_comments_event_id_fkey"
+        | "reminders_event_id_fkey"
+        | "invitations_event_id_fkey"        => ApiError::NotFound("Event not found.".into()),
+        "events_super_event_id_fkey"         => ApiError::Unprocessable("Super event not found.".into()),
+        "events_tags_pkey"                   => ApiError::Unprocessable("Tags are repeated.".into()),
+        "events_tags_tag_id_fkey"            => ApiError::Unprocessable("Tag not found.".into()),
+        "invitations_pending_user_key"
+        | "invitations_pending_email_key"    => ApiError::Conflict("An invitation to the event is already pending.".into()),
+        "invitations_user_id_fkey"           => ApiError::Unprocessable("User not found.".into()),
+        _ => return None,
+    };
+
+    Some(error)
+}
+
+impl From<sqlx::Error> for ApiError
+{
+    fn from(e: sqlx::Error) -> Self
+    {
+        match &e
+        {
+            sqlx::Error::RowNotFound => return ApiError::NotFound("Not found.".into()),
+            sqlx::Error::Database(database_error) =>
+            {
+                if let Some(error) = database_error.constraint().and_then(constraint_error)
+                {
+                    return error;
+                }
+
+                match database_error.kind()
+                {
+                    ErrorKind::UniqueViolation => return ApiError::Conflict("Already exists.".into()),
+                    ErrorKind::ForeignKeyViolation => return ApiError::Unprocessable("A referenced record doesn't exist.".into()),
+                    ErrorKind::CheckViolation => return ApiError::Unprocessable("Invalid value.".into()),
+                    _ => {},
+                }
+
+                // string_data_right_truncation, like too long names
+                if database_error.code().as_deref() == Some("22001")
+                {
+                    return ApiError::Unprocessable("A value is too long.".into());
+                }
+            },
+            _ => {},
+        }
+
+        eprintln!("Database error: {e}");
+
+        ApiError::Internal
+    }
+}
+
+impl From<ValidationErrors> for ApiError
+{
+    fn from(errors: ValidationErrors) -> Self
+    {
+        ApiError::Validation(errors)
+    }
+}
+
+impl From<JsonRejection> for ApiError
+{
+    fn from(rejection: JsonRejection) -> Self
+    {
+        match rejection.status()
+        {
+            StatusCode::UNPROCESSABLE_ENTITY => ApiError::Unprocessable(rejection.body_text()),
+            _ => ApiError::BadRequest(rejection.body_text()),
+        }
+    }
+}
+
+impl From<PathRejection> for ApiError
+{
+    fn from(rejection: PathRejection) -> Self
+    {
+        ApiError::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<MultipartRejection> for ApiError
+{
+    fn from(rejection: MultipartRejection) -> Self
+    {
+        ApiError::BadRequest(rejection.body_text())
+    }
+}
+
+/// Bodies go over their limit while fields are read.
+impl From<MultipartError> for ApiError
+{
+    fn from(error: MultipartError) -> Self
+    {
+        match error.status()
+        {
+            StatusCode::PAYLOAD_TOO_LARGE => ApiError::PayloadTooLarge("The request body is too large.".into()),
+            _ => ApiError::BadRequest(error.body_text()),
+        }
+    }
+}
+
+impl From<QueryRejection> for ApiError
+{
+    fn from(rejection: QueryRejection) -> Self
+    {
+        ApiError::BadRequest(rejection.body_text())
+    }
+}
@@ -0,0 +1,92 @@
+use axum::{
+    async_trait,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use validator::Validate;
+use crate::helpers::error::ApiError;
+
+/// `axum::Json`, rejecting with an [`ApiError`].
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+pub struct Json<T>(pub T);
+
+impl<T: Serialize> IntoResponse for Json<T>
+{
+    fn into_response(self) -> Response
+    {
+        axum::Json(self.0).into_response()
+    }
+}
+
+/// [`Json`] whose content is validated before it reaches the handler.
+pub struct ValidJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection>
+    {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+
+        value.validate()?;
+
+        Ok(ValidJson(value))
+    }
+}
+
+/// `axum::extract::Path`, rejecting with an [`ApiError`].
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+pub struct Path<T>(pub T);
+
+/// `axum::extract::Query`, rejecting with an [`ApiError`].
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ApiError))]
+pub struct Query<T>(pub T);
+
+/// [`Query`] whose content is validated before it reaches the handler.
+pub struct ValidQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidQuery<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection>
+    {
+        let Query(value) = Query::<T>::from_request_parts(parts, state).await?;
+
+        value.validate()?;
+
+        Ok(ValidQuery(value))
+    }
+}
+
+/// `axum::extract::Multipart`, rejecting with an [`ApiError`].
+pub struct Multipart(pub axum::extract::Multipart);
+
+#[async_trait]
+impl<S> FromRequest<S> for Multipart
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection>
+    {
+        let multipart = axum::extract::Multipart::from_request(req, state).await?;
+
+        Ok(Multipart(multipart))
+    }
+}
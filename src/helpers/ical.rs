@@ -0,0 +1,617 @@
+use std::collections::BTreeMap;
+use chrono::{DateTime, Duration, FixedOffset, Offset, TimeZone, Utc};
+use chrono_tz::{OffsetComponents, OffsetName, Tz};
+
+pub const PRODUCT_ID: &str = "-//events-calendar-api//EN";
+
+/// Domain part of the UIDs of the components we publish.
+pub const UID_DOMAIN: &str = "events-calendar-api";
+
+/// How long after the last exported time (or now) time zone transitions are
+/// described, so open-ended recurrences keep their wall-clock time in clients.
+const TIME_ZONE_HORIZON_DAYS: i64 = 3653;
+
+pub fn format_utc(date_time: DateTime<Utc>) -> String
+{
+    date_time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn format_local(date_time: DateTime<Utc>, time_zone: &Tz) -> String
+{
+    date_time.with_timezone(time_zone).format("%Y%m%dT%H%M%S").to_string()
+}
+
+fn format_offset(offset: FixedOffset) -> String
+{
+    let seconds = offset.local_minus_utc();
+    let sign    = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.abs();
+
+    match seconds % 60
+    {
+        0 => format!("{sign}{:02}{:02}", seconds / 3600, seconds % 3600 / 60),
+        s => format!("{sign}{:02}{:02}{s:02}", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+/// Escapes a TEXT value (RFC 5545, section 3.3.11).
+pub fn escape_text(text: &str) -> String
+{
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// CAL-ADDRESS of a user: their email, or an URN for users without one.
+pub fn calendar_address(user_id: i64, email: Option<&str>) -> String
+{
+    match email
+    {
+        Some(email) => format!("mailto:{email}"),
+        None => format!("urn:{UID_DOMAIN}:user:{user_id}"),
+    }
+}
+
+/// Quotes a parameter value, which can't contain double quotes at all.
+pub fn quote_param(value: &str) -> String
+{
+    format!("\"{}\"", value.replace('"', "'"))
+}
+
+/// Writer of an iCalendar object. Content lines are folded at 75 octets, and
+/// the VTIMEZONE components of the time zones referenced by TZID parameters are
+/// generated from their transitions when finishing it.
+pub struct ICalendar
+{
+    output    : String,
+    time_zones: BTreeMap<&'static str, (Tz, DateTime<Utc>, DateTime<Utc>)>,
+}
+
+impl Default for ICalendar
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl ICalendar
+{
+    pub fn new() -> Self
+    {
+        let mut calendar = ICalendar {
+            output    : String::new(),
+            time_zones: BTreeMap::new(),
+        };
+
+        calendar.begin("VCALENDAR");
+        calendar.property("VERSION", "2.0");
+        calendar.property("PRODID", PRODUCT_ID);
+        calendar.property("CALSCALE", "GREGORIAN");
+
+        calendar
+    }
+
+    pub fn begin(&mut self, component: &str)
+    {
+        self.property("BEGIN", component);
+    }
+
+    pub fn end(&mut self, component: &str)
+    {
+        self.property("END", component);
+    }
+
+    /// Writes a content line, whose name may carry parameters.
+    pub fn property(&mut self, name: &str, value: &str)
+    {
+        let line = format!("{name}:{value}");
+        let mut width = 0;
+
+        for character in line.chars()
+        {
+            if width + character.len_utf8() > 75
+            {
+                self.output.push_str("\r\n ");
+                width = 1;
+            }
+
+            self.output.push(character);
+            width += character.len_utf8();
+        }
+
+        self.output.push_str("\r\n");
+    }
+
+    pub fn text(&mut self, name: &str, text: &str)
+    {
+        self.property(name, &escape_text(text));
+    }
+
+    /// Writes a DATE-TIME in UTC, or as a wall-clock time in any other zone.
+    pub fn date_time(&mut self, name: &str, date_time: DateTime<Utc>, time_zone: &Tz)
+    {
+        self.date_times(name, &[date_time], time_zone);
+    }
+
+    pub fn date_times(&mut self, name: &str, date_times: &[DateTime<Utc>], time_zone: &Tz)
+    {
+        if *time_zone == Tz::UTC
+        {
+            let values: Vec<String> = date_times.iter().map(|date_time| format_utc(*date_time)).collect();
+            self.property(name, &values.join(","));
+
+            return;
+        }
+
+        for date_time in date_times
+        {
+            self.time_zones.entry(time_zone.name())
+                .and_modify(|(_, from, to)|
+                {
+                    *from = (*from).min(*date_time);
+                    *to   = (*to).max(*date_time);
+                })
+                .or_insert((*time_zone, *date_time, *date_time));
+        }
+
+        let values: Vec<String> = date_times.iter()
+            .map(|date_time| format_local(*date_time, time_zone))
+            .collect();
+
+        self.property(&format!("{name};TZID={}", time_zone.name()), &values.join(","));
+    }
+
+    fn time_zone(&mut self, time_zone: &Tz, from: DateTime<Utc>, to: DateTime<Utc>)
+    {
+        let from = from - Duration::days(1);
+        let to   = to.max(Utc::now()) + Duration::days(TIME_ZONE_HORIZON_DAYS);
+
+        self.begin("VTIMEZONE");
+        self.property("TZID", time_zone.name());
+
+        self.observance(time_zone, from, time_zone.offset_from_utc_datetime(&from.naive_utc()).fix());
+
+        let mut instant = from;
+        while instant < to
+        {
+            let next = instant + Duration::days(1);
+            let offset = time_zone.offset_from_utc_datetime(&instant.naive_utc());
+
+            if offset != time_zone.offset_from_utc_datetime(&next.naive_utc())
+            {
+                let (mut before, mut after) = (instant, next);
+                while after - before > Duration::seconds(1)
+                {
+                    let middle = before + (after - before) / 2;
+
+                    if time_zone.offset_from_utc_datetime(&middle.naive_utc()) == offset
+                    {
+                        before = middle;
+                    }
+                    else
+                    {
+                        after = middle;
+                    }
+                }
+
+                self.observance(time_zone, after, offset.fix());
+            }
+
+            instant = next;
+        }
+
+        self.end("VTIMEZONE");
+    }
+
+    /// Describes the offset a time zone has from `start` on, as a STANDARD or
+    /// DAYLIGHT observance whose DTSTART is read with the previous offset.
+    fn observance(&mut self, time_zone: &Tz, start: DateTime<Utc>, offset_from: FixedOffset)
+    {
+        let offset    = time_zone.offset_from_utc_datetime(&start.naive_utc());
+        let component = if offset.dst_offset().is_zero() { "STANDARD" } else { "DAYLIGHT" };
+
+        self.begin(component);
+        self.property("DTSTART", &start.with_timezone(&offset_from).format("%Y%m%dT%H%M%S").to_string());
+        self.property("TZOFFSETFROM", &format_offset(offset_from));
+        self.property("TZOFFSETTO", &format_offset(offset.fix()));
+        self.text("TZNAME", offset.abbreviation());
+        self.end(component);
+    }
+
+    pub fn finish(mut self) -> String
+    {
+        for (time_zone, from, to) in std::mem::take(&mut self.time_zones).into_values()
+        {
+            self.time_zone(&time_zone, from, to);
+        }
+
+        self.end("VCALENDAR");
+
+        self.output
+    }
+}
+
+/// Inverse of `escape_text`.
+pub fn unescape_text(text: &str) -> String
+{
+    let mut unescaped = String::with_capacity(text.len());
+    let mut characters = text.chars();
+
+    while let Some(character) = characters.next()
+    {
+        match character
+        {
+            '\\' => match characters.next()
+            {
+                Some('n') | Some('N') => unescaped.push('\n'),
+                Some(escaped) => unescaped.push(escaped),
+                None => {},
+            },
+            character => unescaped.push(character),
+        }
+    }
+
+    unescaped
+}
+
+/// Parses a DURATION value, like `PT1H30M`, `P1D` or `-P1W`, as long as it
+/// fits in a `Duration`.
+pub fn parse_duration(value: &str) -> Option<Duration>
+{
+    let (sign, value) = match value.strip_prefix('-')
+    {
+        Some(value) => (-1, value),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+
+    let mut duration = Duration::zero();
+    let mut number   = String::new();
+    let mut in_time  = false;
+
+    for character in value.strip_prefix('P')?.chars()
+    {
+        match character
+        {
+            '0'..='9' => number.push(character),
+            'T' => in_time = true,
+            unit =>
+            {
+                let amount: i64 = number.parse().ok()?;
+                number.clear();
+
+                let amount = match (unit, in_time)
+                {
+                    ('W', false) => Duration::try_weeks(amount),
+                    ('D', false) => Duration::try_days(amount),
+                    ('H', true)  => Duration::try_hours(amount),
+                    ('M', true)  => Duration::try_minutes(amount),
+                    ('S', true)  => Duration::try_seconds(amount),
+                    _ => return None,
+                };
+
+                duration = duration.checked_add(&amount?)?;
+            },
+        }
+    }
+
+    number.is_empty().then_some(duration * sign)
+}
+
+#[derive(Debug)]
+pub struct Property
+{
+    pub name  : String,
+    pub params: Vec<(String, String)>,
+    pub value : String,
+}
+
+impl Property
+{
+    pub fn param(&self, name: &str) -> Option<&str>
+    {
+        self.params.iter()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Splits a content line at the first colon and semicolons outside quotes.
+    fn parse(line: &str) -> Option<Property>
+    {
+        let mut parts     = Vec::new();
+        let mut part      = String::new();
+        let mut quoted    = false;
+        let mut separator = None;
+
+        for (index, character) in line.char_indices()
+        {
+            match character
+            {
+                '"' => quoted = !quoted,
+                ';' if !quoted => parts.push(std::mem::take(&mut part)),
+                ':' if !quoted =>
+                {
+                    separator = Some(index);
+                    break;
+                },
+                _ => part.push(character),
+            }
+        }
+
+        parts.push(part);
+
+        let value = line[separator? + 1..].to_owned();
+        let mut parts = parts.into_iter();
+        let name = parts.next()?.to_uppercase();
+
+        let params = parts
+            .filter_map(|param| param.split_once('=')
+                .map(|(name, value)| (name.to_uppercase(), value.to_owned())))
+            .collect();
+
+        Some(Property { name, params, value })
+    }
+}
+
+#[derive(Debug)]
+pub struct Component
+{
+    pub name      : String,
+    pub properties: Vec<Property>,
+    pub components: Vec<Component>,
+}
+
+impl Component
+{
+    pub fn property(&self, name: &str) -> Option<&Property>
+    {
+        self.properties.iter().find(|property| property.name == name)
+    }
+
+    pub fn properties<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Property>
+    {
+        self.properties.iter().filter(move |property| property.name == name)
+    }
+}
+
+/// Parses the components of an iCalendar stream, unfolding its content lines.
+pub fn parse(input: &str) -> Result<Vec<Component>, String>
+{
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in input.lines()
+    {
+        match (line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')), lines.last_mut())
+        {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ if line.trim().is_empty() => {},
+            _ => lines.push(line.to_owned()),
+        }
+    }
+
+    let mut stack: Vec<Component> = Vec::new();
+    let mut components = Vec::new();
+
+    for (number, line) in lines.iter().enumerate()
+    {
+        let property = Property::parse(line)
+            .ok_or_else(|| format!("Invalid content line {}: {line}", number + 1))?;
+
+        match property.name.as_str()
+        {
+            "BEGIN" => stack.push(Component {
+                name      : property.value.to_uppercase(),
+                properties: Vec::new(),
+                components: Vec::new(),
+            }),
+            "END" =>
+            {
+                let component = stack.pop()
+                    .filter(|component| component.name.eq_ignore_ascii_case(&property.value))
+                    .ok_or_else(|| format!("Unexpected END:{}", property.value))?;
+
+                match stack.last_mut()
+                {
+                    Some(parent) => parent.components.push(component),
+                    None => components.push(component),
+                }
+            },
+            _ => stack.last_mut()
+                .ok_or_else(|| format!("Property {} outside of a component", property.name))?
+                .properties
+                .push(property),
+        }
+    }
+
+    match stack.pop()
+    {
+        Some(component) => Err(format!("Missing END:{}", component.name)),
+        None => Ok(components),
+    }
+}
+
+/// Splits a list of TEXT values at its unescaped commas, unescaping them.
+pub fn split_text_list(value: &str) -> Vec<String>
+{
+    let mut values  = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+
+    for character in value.chars()
+    {
+        match character
+        {
+            ',' if !escaped => values.push(unescape_text(&std::mem::take(&mut current))),
+            _ => current.push(character),
+        }
+
+        escaped = character == '\\' && !escaped;
+    }
+
+    values.push(unescape_text(&current));
+    values.retain(|value| !value.is_empty());
+
+    values
+}
+
+#[cfg(test)]
+mod tests
+{
+    use chrono_tz::Europe::Berlin;
+    use super::*;
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Utc>
+    {
+        Utc.with_ymd_and_hms(year, month, day, hour, 0, 0).unwrap()
+    }
+
+    /// Writes a single property and parses it back from the folded output.
+    fn round_trip(name: &str, text: &str) -> (String, String)
+    {
+        let mut calendar = ICalendar::new();
+        calendar.begin("VEVENT");
+        calendar.text(name, text);
+        calendar.end("VEVENT");
+
+        let output     = calendar.finish();
+        let components = parse(&output).unwrap();
+        let value      = components[0].components[0].property(name).unwrap().value.clone();
+
+        (output, unescape_text(&value))
+    }
+
+    #[test]
+    fn folds_lines_at_75_octets()
+    {
+        let text = "a".repeat(200);
+        let (output, parsed) = round_trip("DESCRIPTION", &text);
+
+        assert!(output.split("\r\n").all(|line| line.len() <= 75));
+        assert!(output.contains("\r\n a"));
+        assert_eq!(parsed, text);
+    }
+
+    #[test]
+    fn folds_multi_byte_text_between_characters()
+    {
+        let text = "日本語のテキスト🎉é".repeat(20);
+        let (output, parsed) = round_trip("SUMMARY", &text);
+
+        for line in output.split("\r\n")
+        {
+            assert!(line.len() <= 75, "line of {} octets: {line}", line.len());
+        }
+
+        assert_eq!(parsed, text);
+    }
+
+    #[test]
+    fn escapes_and_unescapes_text()
+    {
+        let text = "a,b;c\\d\ne\r\nf";
+
+        assert_eq!(escape_text(text), "a\\,b\\;c\\\\d\\ne\\nf");
+        assert_eq!(unescape_text(&escape_text(text)), "a,b;c\\d\ne\nf");
+        assert_eq!(unescape_text("line\\Nbreak"), "line\nbreak");
+
+        let (_, parsed) = round_trip("LOCATION", "Room 1; floor 2, \\ building\nnorth");
+        assert_eq!(parsed, "Room 1; floor 2, \\ building\nnorth");
+    }
+
+    #[test]
+    fn splits_text_lists_at_unescaped_commas()
+    {
+        assert_eq!(split_text_list("work,home"), vec!["work", "home"]);
+        assert_eq!(split_text_list("a\\,b,c"), vec!["a,b", "c"]);
+        assert_eq!(split_text_list("a\\\\,b"), vec!["a\\", "b"]);
+        assert_eq!(split_text_list(",a,,b,"), vec!["a", "b"]);
+        assert!(split_text_list("").is_empty());
+    }
+
+    #[test]
+    fn parses_durations()
+    {
+        assert_eq!(parse_duration("PT1H30M"), Some(Duration::minutes(90)));
+        assert_eq!(parse_duration("P1D"), Some(Duration::days(1)));
+        assert_eq!(parse_duration("-P1W"), Some(-Duration::weeks(1)));
+        assert_eq!(parse_duration("+P1DT2H3M4S"), Some(Duration::seconds(93784)));
+        assert_eq!(parse_duration("PT0S"), Some(Duration::zero()));
+
+        assert_eq!(parse_duration("1H"), None);
+        assert_eq!(parse_duration("P1H"), None);
+        assert_eq!(parse_duration("PT1D"), None);
+        assert_eq!(parse_duration("PT1"), None);
+        assert_eq!(parse_duration("PTH"), None);
+        assert_eq!(parse_duration("P99999999999999W"), None);
+        assert_eq!(parse_duration("P99999999999999999999D"), None);
+    }
+
+    #[test]
+    fn parses_quoted_parameters()
+    {
+        let components = parse("BEGIN:VEVENT\r\nATTENDEE;CN=\"Doe; John: Jr\";ROLE=CHAIR:mailto:j@example.com\r\nEND:VEVENT\r\n").unwrap();
+        let attendee   = components[0].property("ATTENDEE").unwrap();
+
+        assert_eq!(attendee.param("cn"), Some("Doe; John: Jr"));
+        assert_eq!(attendee.param("ROLE"), Some("CHAIR"));
+        assert_eq!(attendee.value, "mailto:j@example.com");
+
+        assert!(parse("BEGIN:VEVENT\r\n").is_err());
+        assert!(parse("BEGIN:VEVENT\r\nEND:VTODO\r\n").is_err());
+    }
+
+    #[test]
+    fn writes_utc_times_without_time_zones()
+    {
+        let mut calendar = ICalendar::new();
+        calendar.date_time("DTSTART", utc(2026, 3, 1, 9), &Tz::UTC);
+
+        let output = calendar.finish();
+
+        assert!(output.contains("DTSTART:20260301T090000Z\r\n"));
+        assert!(!output.contains("VTIMEZONE"));
+    }
+
+    #[test]
+    fn generates_time_zones_with_their_transitions()
+    {
+        let mut calendar = ICalendar::new();
+        calendar.date_times("EXDATE", &[utc(2026, 3, 1, 9), utc(2026, 7, 1, 8)], &Berlin);
+
+        let output     = calendar.finish();
+        let components = parse(&output).unwrap();
+        let time_zone  = components[0].components.iter()
+            .find(|component| component.name == "VTIMEZONE")
+            .unwrap();
+
+        assert!(output.contains("EXDATE;TZID=Europe/Berlin:20260301T100000,20260701T100000\r\n"));
+        assert_eq!(time_zone.property("TZID").unwrap().value, "Europe/Berlin");
+
+        let observance = |name: &str, start: &str| time_zone.components.iter()
+            .find(|component| component.name == name && component.property("DTSTART").unwrap().value == start);
+
+        let summer = observance("DAYLIGHT", "20260329T020000").unwrap();
+        assert_eq!(summer.property("TZOFFSETFROM").unwrap().value, "+0100");
+        assert_eq!(summer.property("TZOFFSETTO").unwrap().value, "+0200");
+        assert_eq!(summer.property("TZNAME").unwrap().value, "CEST");
+
+        let winter = observance("STANDARD", "20261025T030000").unwrap();
+        assert_eq!(winter.property("TZOFFSETFROM").unwrap().value, "+0200");
+        assert_eq!(winter.property("TZOFFSETTO").unwrap().value, "+0100");
+
+        // the first observance covers the offset before the first time
+        let first = &time_zone.components[0];
+        assert_eq!(first.name, "STANDARD");
+        assert_eq!(first.property("DTSTART").unwrap().value, "20260228T100000");
+    }
+
+    #[test]
+    fn formats_offsets()
+    {
+        assert_eq!(format_offset(FixedOffset::east_opt(5 * 3600 + 30 * 60).unwrap()), "+0530");
+        assert_eq!(format_offset(FixedOffset::west_opt(3 * 3600).unwrap()), "-0300");
+        assert_eq!(format_offset(FixedOffset::east_opt(3600 + 15).unwrap()), "+010015");
+    }
+}
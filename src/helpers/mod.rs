@@ -1 +1,10 @@
-pub mod error;
\ No newline at end of file
+pub mod auth;
+pub mod conflict;
+pub mod error;
+pub mod extract;
+pub mod ical;
+pub mod policy;
+pub mod recurrence;
+pub mod template;
+pub mod time_zone;
+pub mod validation;
\ No newline at end of file
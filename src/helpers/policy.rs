@@ -0,0 +1,217 @@
+use serde::Serialize;
+use sqlx::PgExecutor;
+use crate::helpers::error::ApiError;
+
+/// How a user relates to an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role
+{
+    Owner,
+    Participant,
+    Outsider,
+}
+
+impl Role
+{
+    /// From the `owner` flag of a `users_events` row, missing for outsiders.
+    fn from_owner(owner: Option<bool>) -> Self
+    {
+        match owner
+        {
+            Some(true)  => Role::Owner,
+            Some(false) => Role::Participant,
+            None        => Role::Outsider,
+        }
+    }
+}
+
+/// What a user may try to do with an event or its comments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action
+{
+    /// Read the event, its schedules, comments and calendar.
+    View,
+    Comment,
+    /// Add oneself as a participant.
+    Join,
+    /// Remove oneself from the participants.
+    Leave,
+    /// Answer whether one attends, only for oneself.
+    Respond,
+    /// Change the event, its schedules and recurrences.
+    Edit,
+    Delete,
+    /// Add or remove other participants.
+    ManageParticipants,
+    EditComment,
+    DeleteComment,
+    /// Upload files to the event or to one's comments.
+    Attach,
+    /// List and download the files of the event.
+    Download,
+}
+
+impl Action
+{
+    pub fn description(&self) -> &'static str
+    {
+        match self
+        {
+            Action::View               => "view this event",
+            Action::Comment            => "comment on this event",
+            Action::Join               => "join this event",
+            Action::Leave              => "leave this event",
+            Action::Respond            => "respond to this event",
+            Action::Edit               => "edit this event",
+            Action::Delete             => "delete this event",
+            Action::ManageParticipants => "manage the participants of this event",
+            Action::EditComment        => "edit this comment",
+            Action::DeleteComment      => "delete this comment",
+            Action::Attach             => "attach files to this event",
+            Action::Download           => "download the files of this event",
+        }
+    }
+}
+
+/// The role of a user in an event, along with what decides the rules.
+#[derive(Debug, Clone, Copy)]
+pub struct EventAccess
+{
+    pub role   : Role,
+    pub private: bool,
+}
+
+impl EventAccess
+{
+    /// Private events only exist for their participants. Everything else than
+    /// seeing, commenting and joining public events is up to the owners, but
+    /// editing comments, which is left to their authors (see
+    /// [`authorize_comment`]), and files, which are shared among participants.
+    pub fn allows(&self, action: Action) -> bool
+    {
+        let visible = !self.private || self.role != Role::Outsider;
+
+        match action
+        {
+            Action::View | Action::Comment  => visible,
+            Action::Join                    => !self.private,
+            Action::Leave
+            | Action::Respond
+            | Action::Attach
+            | Action::Download              => self.role != Role::Outsider,
+            Action::EditComment             => false,
+            Action::Edit
+            | Action::Delete
+            | Action::ManageParticipants
+            | Action::DeleteComment         => self.role == Role::Owner,
+        }
+    }
+}
+
+/// The access of a user to an event, or `None` when it doesn't exist.
+pub async fn event_access(
+    executor: impl PgExecutor<'_>,
+    user_id: i64,
+    event_id: i64,
+) -> Result<Option<EventAccess>, sqlx::Error>
+{
+    let access = sqlx::query!(
+        r#"SELECT events.private, users_events.owner AS "owner?"
+        FROM events
+        LEFT JOIN users_events ON users_events.event_id = events.id AND users_events.user_id = $2
+        WHERE events.id = $1"#,
+        event_id,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(access.map(|access| EventAccess {
+        role   : Role::from_owner(access.owner),
+        private: access.private,
+    }))
+}
+
+/// Checks that a user may do something with an event, answering 404 when it
+/// doesn't exist and 403 when it isn't allowed.
+pub async fn authorize_event(
+    executor: impl PgExecutor<'_>,
+    user_id: i64,
+    event_id: i64,
+    action: Action,
+) -> Result<EventAccess, ApiError>
+{
+    let access = event_access(executor, user_id, event_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Event not found.".into()))?;
+
+    match access.allows(action)
+    {
+        true  => Ok(access),
+        false => Err(ApiError::Forbidden { action, role: access.role }),
+    }
+}
+
+/// Checks that a user may edit or delete a comment, giving the event it
+/// belongs to. Authors can do both while they can still see the event, and
+/// owners of the event can delete any comment. Deleted comments are only
+/// tombstones, not found anymore.
+pub async fn authorize_comment(
+    executor: impl PgExecutor<'_>,
+    user_id: i64,
+    comment_id: i64,
+    action: Action,
+) -> Result<i64, ApiError>
+{
+    let comment = sqlx::query!(
+        r#"SELECT events_comments.event_id, events_comments.user_id = $2 AS "author!",
+            events.private, users_events.owner AS "owner?"
+        FROM events_comments
+        JOIN events ON events.id = events_comments.event_id
+        LEFT JOIN users_events ON users_events.event_id = events.id AND users_events.user_id = $2
+        WHERE events_comments.id = $1 AND events_comments.deleted_at IS NULL"#,
+        comment_id,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Comment not found.".into()))?;
+
+    let access = EventAccess {
+        role   : Role::from_owner(comment.owner),
+        private: comment.private,
+    };
+
+    if (comment.author && access.allows(Action::View)) || access.allows(action)
+    {
+        Ok(comment.event_id)
+    }
+    else
+    {
+        Err(ApiError::Forbidden { action, role: access.role })
+    }
+}
+
+/// Keeps the events a user can see, like for listing what another user takes
+/// part in.
+pub async fn visible_event_ids(
+    executor: impl PgExecutor<'_>,
+    user_id: i64,
+    event_ids: &[i64],
+) -> Result<Vec<i64>, sqlx::Error>
+{
+    sqlx::query_scalar!(
+        "SELECT id FROM events
+        WHERE id = ANY($1)
+            AND (NOT private OR EXISTS (
+                SELECT 1 FROM users_events WHERE users_events.event_id = events.id AND users_events.user_id = $2
+            ))
+        ORDER BY id",
+        event_ids,
+        user_id
+    )
+    .fetch_all(executor)
+    .await
+}
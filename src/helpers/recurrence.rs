@@ -0,0 +1,781 @@
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::{PgHasArrayType, PgTypeInfo}, FromRow, PgConnection, PgExecutor, PgPool};
+use chrono::{DateTime, Duration, FixedOffset, TimeZone, Utc};
+use rrule::{Frequency, NWeekday, RRule, RRuleError, RRuleSet, Tz, Unvalidated};
+use crate::helpers::{ical, time_zone::in_time_zone};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, sqlx::Type, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "week_day", rename_all = "lowercase")]
+pub enum Weekday
+{
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+impl From<chrono::Weekday> for Weekday
+{
+    fn from(weekday: chrono::Weekday) -> Self
+    {
+        match weekday {
+            chrono::Weekday::Mon => Weekday::Monday,
+            chrono::Weekday::Tue => Weekday::Tuesday,
+            chrono::Weekday::Wed => Weekday::Wednesday,
+            chrono::Weekday::Thu => Weekday::Thursday,
+            chrono::Weekday::Fri => Weekday::Friday,
+            chrono::Weekday::Sat => Weekday::Saturday,
+            chrono::Weekday::Sun => Weekday::Sunday,
+        }
+    }
+}
+impl From<Weekday> for chrono::Weekday
+{
+    fn from(weekday: Weekday) -> Self
+    {
+        match weekday {
+            Weekday::Monday    => chrono::Weekday::Mon,
+            Weekday::Tuesday   => chrono::Weekday::Tue,
+            Weekday::Wednesday => chrono::Weekday::Wed,
+            Weekday::Thursday  => chrono::Weekday::Thu,
+            Weekday::Friday    => chrono::Weekday::Fri,
+            Weekday::Saturday  => chrono::Weekday::Sat,
+            Weekday::Sunday    => chrono::Weekday::Sun,
+        }
+    }
+}
+impl PgHasArrayType for Weekday
+{
+    fn array_type_info() -> PgTypeInfo
+    {
+        PgTypeInfo::with_name("_week_day")
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "recurrence_type", rename_all = "lowercase")]
+pub enum RecurrenceType
+{
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+    Rule,
+}
+
+/// A stored recurrence, whose occurrences are computed on read in the time zone
+/// of its event, so they keep their wall-clock time across DST transitions.
+#[derive(Debug, FromRow, Serialize)]
+pub struct Recurrence
+{
+    pub id          : i64,
+    pub event_id    : i64,
+    pub r#type      : RecurrenceType,
+    pub step        : i16,
+    pub repetitions : Option<i16>,
+    pub end_date    : Option<DateTime<FixedOffset>>,
+    pub rrule       : Option<String>,
+    pub start_time  : DateTime<FixedOffset>,
+    pub end_time    : DateTime<FixedOffset>,
+    pub exdates     : Vec<DateTime<FixedOffset>>,
+    pub days_of_week: Vec<Weekday>,
+    pub time_zone   : String,
+}
+
+/// Either a stored schedule (an individual event or an edited occurrence of a
+/// recurrence) or an occurrence generated from a recurrence, which has no `id`.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Schedule
+{
+    pub id                 : Option<i64>,
+    pub recurrence_id      : Option<i64>,
+    pub event_id           : i64,
+    pub start_time         : DateTime<FixedOffset>,
+    pub end_time           : DateTime<FixedOffset>,
+    pub original_start_time: Option<DateTime<FixedOffset>>,
+}
+
+/// Upper bound of occurrences generated for a single recurrence in one window.
+const EXPANSION_LIMIT: u16 = u16::MAX;
+
+fn to_rrule_tz<T: TimeZone>(date_time: DateTime<T>, time_zone: &Tz) -> DateTime<Tz>
+{
+    date_time.with_timezone(time_zone)
+}
+
+/// Parses and validates a RRULE against the start of its series.
+pub fn parse_rrule(
+    rrule: &str,
+    start_time: DateTime<Utc>,
+    time_zone: chrono_tz::Tz,
+) -> Result<RRuleSet, RRuleError>
+{
+    rrule.trim()
+        .trim_start_matches("RRULE:")
+        .parse::<RRule<Unvalidated>>()?
+        .build(to_rrule_tz(start_time, &Tz::Tz(time_zone)))
+}
+
+/// Whether a RRULE repeats more than once a day, by its frequency or by several
+/// hours, minutes or seconds, which would make windows too costly to expand.
+/// Rules that don't parse are left to `parse_rrule`.
+pub fn is_sub_daily(rrule: &str) -> bool
+{
+    let Ok(rrule) = rrule.trim().trim_start_matches("RRULE:").parse::<RRule<Unvalidated>>() else
+    {
+        return false;
+    };
+
+    matches!(rrule.get_freq(), Frequency::Hourly | Frequency::Minutely | Frequency::Secondly)
+        || rrule.get_by_hour().len() > 1
+        || rrule.get_by_minute().len() > 1
+        || rrule.get_by_second().len() > 1
+}
+
+impl Recurrence
+{
+    /// Event time zones are only written from parsed ones, so they are always valid.
+    pub fn time_zone(&self) -> chrono_tz::Tz
+    {
+        self.time_zone.parse().unwrap_or(chrono_tz::Tz::UTC)
+    }
+
+    pub fn duration(&self) -> Duration
+    {
+        self.end_time - self.start_time
+    }
+
+    /// Builds the RRULE set equivalent to this recurrence, exdates included.
+    pub fn rrule_set(&self) -> Result<RRuleSet, RRuleError>
+    {
+        let time_zone = self.time_zone();
+        let dt_start  = to_rrule_tz(self.start_time, &Tz::Tz(time_zone));
+
+        let rrule_set = match self.r#type
+        {
+            RecurrenceType::Rule => parse_rrule(
+                self.rrule.as_deref().unwrap_or_default(),
+                self.start_time.with_timezone(&Utc),
+                time_zone,
+            )?,
+            RecurrenceType::Weekly =>
+            {
+                let mut rrule = RRule::new(Frequency::Weekly)
+                    .interval(self.step as u16)
+                    .by_weekday(self.days_of_week.iter()
+                        .map(|day_of_week| NWeekday::Every((*day_of_week).into()))
+                        .collect());
+
+                // weekly repetitions count weeks rather than occurrences
+                if let Some(repetitions) = self.repetitions
+                {
+                    let until = self.start_time + Duration::weeks(self.step as i64 * repetitions as i64);
+                    rrule = rrule.until(to_rrule_tz(until, &Tz::UTC));
+                }
+
+                rrule.build(dt_start)?
+            },
+            RecurrenceType::Daily | RecurrenceType::Monthly | RecurrenceType::Yearly =>
+            {
+                let frequency = match self.r#type
+                {
+                    RecurrenceType::Daily   => Frequency::Daily,
+                    RecurrenceType::Monthly => Frequency::Monthly,
+                    _                       => Frequency::Yearly,
+                };
+
+                let mut rrule = RRule::new(frequency).interval(self.step as u16);
+
+                if let Some(repetitions) = self.repetitions
+                {
+                    rrule = rrule.count(repetitions as u32);
+                }
+
+                rrule.build(dt_start)?
+            },
+        };
+
+        Ok(rrule_set.set_exdates(self.exdates.iter()
+            .map(|exdate| to_rrule_tz(*exdate, &Tz::Tz(time_zone)))
+            .collect()))
+    }
+
+    /// Start times of the occurrences overlapping the `[from, to)` window. The
+    /// `end_date` works as a cap over the rule, so a series can be cut short
+    /// without rewriting it. A stored rule that can't be built is reported as a
+    /// decoding error.
+    pub fn occurrences(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<DateTime<Utc>>, sqlx::Error>
+    {
+        let duration = self.duration();
+        let before   = match self.end_date
+        {
+            Some(end_date) if end_date < to => end_date.with_timezone(&Utc),
+            _ => to,
+        };
+
+        if before < from - duration
+        {
+            return Ok(Vec::new());
+        }
+
+        let occurrences = self.rrule_set()
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))?
+            .after(to_rrule_tz(from - duration, &Tz::UTC))
+            .before(to_rrule_tz(before, &Tz::UTC))
+            .all(EXPANSION_LIMIT)
+            .dates
+            .into_iter()
+            .map(|occurrence| occurrence.with_timezone(&Utc))
+            .filter(|start_time| *start_time < to && *start_time + duration > from)
+            .collect();
+
+        Ok(occurrences)
+    }
+
+    /// RRULE value equivalent to this recurrence, for exporting it to other
+    /// calendar systems. A capped series gets its last occurrence as UNTIL.
+    pub fn ical_rrule(&self) -> Result<String, sqlx::Error>
+    {
+        let mut parts: Vec<String> = match self.r#type
+        {
+            RecurrenceType::Rule => self.rrule.as_deref()
+                .unwrap_or_default()
+                .trim()
+                .trim_start_matches("RRULE:")
+                .split(';')
+                .filter(|part| !part.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            _ =>
+            {
+                let frequency = match self.r#type
+                {
+                    RecurrenceType::Daily   => "DAILY",
+                    RecurrenceType::Weekly  => "WEEKLY",
+                    RecurrenceType::Monthly => "MONTHLY",
+                    _                       => "YEARLY",
+                };
+
+                let mut parts = vec![format!("FREQ={frequency}"), format!("INTERVAL={}", self.step)];
+
+                if let RecurrenceType::Weekly = self.r#type
+                {
+                    parts.push(format!("BYDAY={}", self.days_of_week.iter()
+                        .map(|day_of_week| NWeekday::Every((*day_of_week).into()).to_string())
+                        .collect::<Vec<_>>()
+                        .join(",")));
+
+                    if let Some(repetitions) = self.repetitions
+                    {
+                        let until = self.start_time + Duration::weeks(self.step as i64 * repetitions as i64);
+                        parts.push(format!("UNTIL={}", ical::format_utc(until.with_timezone(&Utc))));
+                    }
+                }
+                else if let Some(repetitions) = self.repetitions
+                {
+                    parts.push(format!("COUNT={repetitions}"));
+                }
+
+                parts
+            },
+        };
+
+        if let Some(end_date) = self.end_date
+        {
+            let until = self.rrule_set()
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))?
+                .before(to_rrule_tz(end_date, &Tz::UTC))
+                .all(EXPANSION_LIMIT)
+                .dates
+                .last()
+                .map_or(end_date.with_timezone(&Utc), |last| last.with_timezone(&Utc));
+
+            parts.retain(|part| !part.starts_with("COUNT=") && !part.starts_with("UNTIL="));
+            parts.push(format!("UNTIL={}", ical::format_utc(until)));
+        }
+
+        Ok(parts.join(";"))
+    }
+
+    /// Whether `start_time` is one of the occurrences generated by this recurrence.
+    pub fn has_occurrence(&self, start_time: DateTime<Utc>) -> Result<bool, sqlx::Error>
+    {
+        Ok(self.occurrences(start_time - Duration::seconds(1), start_time + Duration::seconds(1))?
+            .contains(&start_time))
+    }
+
+    pub fn in_time_zone(self, time_zone: &chrono_tz::Tz) -> Self
+    {
+        Recurrence {
+            end_date  : self.end_date.map(|end_date| in_time_zone(end_date, time_zone)),
+            start_time: in_time_zone(self.start_time, time_zone),
+            end_time  : in_time_zone(self.end_time, time_zone),
+            exdates   : self.exdates.into_iter()
+                .map(|exdate| in_time_zone(exdate, time_zone))
+                .collect(),
+            ..self
+        }
+    }
+}
+
+impl Schedule
+{
+    pub fn in_time_zone(self, time_zone: &chrono_tz::Tz) -> Self
+    {
+        Schedule {
+            start_time         : in_time_zone(self.start_time, time_zone),
+            end_time           : in_time_zone(self.end_time, time_zone),
+            original_start_time: self.original_start_time
+                .map(|original_start_time| in_time_zone(original_start_time, time_zone)),
+            ..self
+        }
+    }
+}
+
+const SELECT_RECURRENCES: &str =
+    "SELECT recurrences.*, events.time_zone,
+        ARRAY_REMOVE(ARRAY_AGG(recurrences_week_days.week_day), NULL) AS days_of_week
+    FROM recurrences
+    JOIN events ON events.id = recurrences.event_id
+    LEFT JOIN recurrences_week_days ON recurrences_week_days.recurrence_id = recurrences.id";
+
+pub async fn find_recurrence(
+    executor: impl PgExecutor<'_>,
+    id: i64,
+) -> Result<Option<Recurrence>, sqlx::Error>
+{
+    sqlx::query_as::<_, Recurrence>(&format!(
+        "{SELECT_RECURRENCES}
+        WHERE recurrences.id = $1
+        GROUP BY recurrences.id, events.time_zone"))
+        .bind(id)
+        .fetch_optional(executor)
+        .await
+}
+
+pub async fn list_recurrences(
+    executor: impl PgExecutor<'_>,
+    event_ids: &[i64],
+) -> Result<Vec<Recurrence>, sqlx::Error>
+{
+    sqlx::query_as::<_, Recurrence>(&format!(
+        "{SELECT_RECURRENCES}
+        WHERE recurrences.event_id = ANY($1)
+        GROUP BY recurrences.id, events.time_zone
+        ORDER BY recurrences.start_time"))
+        .bind(event_ids)
+        .fetch_all(executor)
+        .await
+}
+
+/// Lists the schedules of the given events overlapping the `[from, to)` window,
+/// merging the stored ones with the occurrences generated from recurrences that
+/// weren't cancelled or replaced by an edited schedule.
+pub async fn list_schedules(
+    connection: &mut PgConnection,
+    event_ids: &[i64],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<Schedule>, sqlx::Error>
+{
+    let mut schedules = sqlx::query_as::<_, Schedule>
+        ("SELECT id, recurrence_id, event_id, start_time, end_time, original_start_time
+        FROM schedules
+        WHERE event_id = ANY($1) AND start_time < $3 AND end_time > $2")
+        .bind(event_ids)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&mut *connection)
+        .await?;
+
+    let recurrences = list_recurrences(&mut *connection, event_ids).await?;
+
+    let recurrence_ids: Vec<i64> = recurrences.iter().map(|recurrence| recurrence.id).collect();
+
+    let overridden: HashSet<(i64, DateTime<Utc>)> = sqlx::query_as::<_, (i64, DateTime<Utc>)>
+        ("SELECT recurrence_id, original_start_time FROM schedules
+        WHERE recurrence_id = ANY($1) AND original_start_time IS NOT NULL")
+        .bind(&recurrence_ids)
+        .fetch_all(&mut *connection)
+        .await?
+        .into_iter()
+        .collect();
+
+    for recurrence in recurrences
+    {
+        let duration = recurrence.duration();
+
+        schedules.extend(recurrence.occurrences(from, to)?
+            .into_iter()
+            .filter(|start_time| !overridden.contains(&(recurrence.id, *start_time)))
+            .map(|start_time| Schedule {
+                id                 : None,
+                recurrence_id      : Some(recurrence.id),
+                event_id           : recurrence.event_id,
+                start_time         : start_time.fixed_offset(),
+                end_time           : (start_time + duration).fixed_offset(),
+                original_start_time: Some(start_time.fixed_offset()),
+            }));
+    }
+
+    schedules.sort_by_key(|schedule| schedule.start_time);
+
+    Ok(schedules)
+}
+
+
+type Times = (DateTime<Utc>, DateTime<Utc>);
+
+/// How occurrences materialized before recurrences were expanded on read fit
+/// what their recurrence generates.
+#[derive(Debug, Default, PartialEq)]
+struct Adoption
+{
+    /// Generated occurrences that had been deleted.
+    exdates : Vec<DateTime<Utc>>,
+    /// Edited occurrences, with the original start time they replace.
+    edited  : Vec<(DateTime<Utc>, Times)>,
+    /// Schedules the recurrence can't have generated, kept on their own.
+    detached: Vec<Times>,
+}
+
+/// Schedules starting at a generated time are that occurrence, moved ones are
+/// paired with the closest generated time left, in order. Generated times left
+/// without a schedule had been deleted.
+fn adopt(generated: &[DateTime<Utc>], duration: Duration, schedules: &[Times]) -> Adoption
+{
+    let mut adoption = Adoption::default();
+    let mut left: Vec<DateTime<Utc>> = generated.to_vec();
+    let mut moved = Vec::new();
+
+    for &(start_time, end_time) in schedules
+    {
+        match left.iter().position(|generated| *generated == start_time)
+        {
+            Some(index) =>
+            {
+                let original = left.remove(index);
+
+                if end_time - start_time != duration
+                {
+                    adoption.edited.push((original, (start_time, end_time)));
+                }
+            },
+            None => moved.push((start_time, end_time)),
+        }
+    }
+
+    for (start_time, end_time) in moved
+    {
+        let closest = left.iter()
+            .enumerate()
+            .min_by_key(|(_, generated)| (**generated - start_time).abs())
+            .map(|(index, _)| index);
+
+        match closest
+        {
+            Some(index) => adoption.edited.push((left.remove(index), (start_time, end_time))),
+            None        => adoption.detached.push((start_time, end_time)),
+        }
+    }
+
+    adoption.exdates = left;
+    adoption
+}
+
+/// Turns the schedules the migration to expansion on read set aside into the
+/// exdates and edited occurrences of their recurrences, one recurrence at a
+/// time. Does nothing once they are all adopted.
+pub async fn adopt_legacy_schedules(db_pool: &PgPool) -> Result<(), sqlx::Error>
+{
+    let recurrence_ids = sqlx::query_scalar!("SELECT DISTINCT recurrence_id FROM legacy_schedules")
+        .fetch_all(db_pool)
+        .await?;
+
+    for recurrence_id in recurrence_ids
+    {
+        let mut transaction = db_pool.begin().await?;
+
+        let schedules: Vec<Times> = sqlx::query!(
+            "DELETE FROM legacy_schedules WHERE recurrence_id = $1 RETURNING start_time, end_time",
+            recurrence_id
+        )
+        .fetch_all(&mut *transaction)
+        .await?
+        .into_iter()
+        .map(|schedule| (schedule.start_time, schedule.end_time))
+        .collect();
+
+        let (Some(recurrence), Some(last)) = (
+            find_recurrence(&mut *transaction, recurrence_id).await?,
+            schedules.iter().map(|(start_time, _)| *start_time).max(),
+        ) else
+        {
+            transaction.commit().await?;
+            continue;
+        };
+
+        // unbounded rules were only materialized up to a limit, and go on after it
+        let until = recurrence.end_date.map_or(last, |end_date| last.max(end_date.to_utc()));
+
+        let generated = recurrence.occurrences(recurrence.start_time.to_utc(), until + Duration::seconds(1))?;
+        let adoption  = adopt(&generated, recurrence.duration(), &schedules);
+
+        sqlx::query!(
+            "UPDATE recurrences SET exdates = exdates || $1 WHERE id = $2",
+            &adoption.exdates,
+            recurrence_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        for (original_start_time, (start_time, end_time)) in adoption.edited
+        {
+            sqlx::query!(
+                "INSERT INTO schedules (event_id, recurrence_id, start_time, end_time, original_start_time)
+                VALUES ($1, $2, $3, $4, $5)",
+                recurrence.event_id,
+                recurrence_id,
+                start_time,
+                end_time,
+                original_start_time
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+
+        for (start_time, end_time) in adoption.detached
+        {
+            sqlx::query!(
+                "INSERT INTO schedules (event_id, start_time, end_time) VALUES ($1, $2, $3)",
+                recurrence.event_id,
+                start_time,
+                end_time
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+
+        transaction.commit().await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use chrono::TimeZone;
+    use chrono_tz::{Europe::Berlin, Tz as ChronoTz};
+    use super::*;
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc>
+    {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap()
+    }
+
+    fn local(time_zone: ChronoTz, year: i32, month: u32, day: u32, hour: u32) -> DateTime<FixedOffset>
+    {
+        time_zone.with_ymd_and_hms(year, month, day, hour, 0, 0).unwrap().fixed_offset()
+    }
+
+    /// An hour long recurrence, starting at `start_time`.
+    fn recurrence(r#type: RecurrenceType, start_time: DateTime<FixedOffset>, time_zone: ChronoTz) -> Recurrence
+    {
+        Recurrence {
+            id          : 1,
+            event_id    : 1,
+            r#type,
+            step        : 1,
+            repetitions : None,
+            end_date    : None,
+            rrule       : None,
+            start_time,
+            end_time    : start_time + Duration::hours(1),
+            exdates     : Vec::new(),
+            days_of_week: Vec::new(),
+            time_zone   : time_zone.name().to_owned(),
+        }
+    }
+
+    fn all_occurrences(recurrence: &Recurrence) -> Vec<DateTime<Utc>>
+    {
+        recurrence.occurrences(utc(2000, 1, 1, 0, 0), utc(2100, 1, 1, 0, 0)).unwrap()
+    }
+
+    #[test]
+    fn daily_repetitions_are_a_count()
+    {
+        let mut daily = recurrence(RecurrenceType::Daily, local(ChronoTz::UTC, 2026, 1, 1, 9), ChronoTz::UTC);
+        daily.step        = 2;
+        daily.repetitions = Some(3);
+
+        assert_eq!(all_occurrences(&daily), vec![
+            utc(2026, 1, 1, 9, 0),
+            utc(2026, 1, 3, 9, 0),
+            utc(2026, 1, 5, 9, 0),
+        ]);
+        assert_eq!(daily.ical_rrule().unwrap(), "FREQ=DAILY;INTERVAL=2;COUNT=3");
+    }
+
+    #[test]
+    fn monthly_and_yearly_repetitions_are_a_count()
+    {
+        let mut monthly = recurrence(RecurrenceType::Monthly, local(ChronoTz::UTC, 2026, 1, 15, 9), ChronoTz::UTC);
+        monthly.repetitions = Some(3);
+
+        assert_eq!(all_occurrences(&monthly), vec![
+            utc(2026, 1, 15, 9, 0),
+            utc(2026, 2, 15, 9, 0),
+            utc(2026, 3, 15, 9, 0),
+        ]);
+        assert_eq!(monthly.ical_rrule().unwrap(), "FREQ=MONTHLY;INTERVAL=1;COUNT=3");
+
+        let mut yearly = recurrence(RecurrenceType::Yearly, local(ChronoTz::UTC, 2026, 6, 1, 9), ChronoTz::UTC);
+        yearly.step        = 2;
+        yearly.repetitions = Some(2);
+
+        assert_eq!(all_occurrences(&yearly), vec![utc(2026, 6, 1, 9, 0), utc(2028, 6, 1, 9, 0)]);
+        assert_eq!(yearly.ical_rrule().unwrap(), "FREQ=YEARLY;INTERVAL=2;COUNT=2");
+    }
+
+    #[test]
+    fn weekly_repetitions_count_weeks_up_to_an_until()
+    {
+        // Monday, every other week on Mondays and Wednesdays, for two periods
+        let mut weekly = recurrence(RecurrenceType::Weekly, local(ChronoTz::UTC, 2026, 1, 5, 9), ChronoTz::UTC);
+        weekly.step         = 2;
+        weekly.repetitions  = Some(2);
+        weekly.days_of_week = vec![Weekday::Monday, Weekday::Wednesday];
+
+        // the until, four weeks after the start, takes the occurrence starting then
+        assert_eq!(all_occurrences(&weekly), vec![
+            utc(2026, 1, 5, 9, 0),
+            utc(2026, 1, 7, 9, 0),
+            utc(2026, 1, 19, 9, 0),
+            utc(2026, 1, 21, 9, 0),
+            utc(2026, 2, 2, 9, 0),
+        ]);
+        assert_eq!(weekly.ical_rrule().unwrap(), "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE;UNTIL=20260202T090000Z");
+    }
+
+    #[test]
+    fn end_date_caps_the_series()
+    {
+        let mut daily = recurrence(RecurrenceType::Daily, local(ChronoTz::UTC, 2026, 1, 1, 9), ChronoTz::UTC);
+        daily.repetitions = Some(10);
+        daily.end_date    = Some(local(ChronoTz::UTC, 2026, 1, 4, 0));
+
+        assert_eq!(all_occurrences(&daily), vec![
+            utc(2026, 1, 1, 9, 0),
+            utc(2026, 1, 2, 9, 0),
+            utc(2026, 1, 3, 9, 0),
+        ]);
+        // the count gives way to the last occurrence before the end date
+        assert_eq!(daily.ical_rrule().unwrap(), "FREQ=DAILY;INTERVAL=1;UNTIL=20260103T090000Z");
+    }
+
+    #[test]
+    fn occurrences_overlap_the_window()
+    {
+        let daily = recurrence(RecurrenceType::Daily, local(ChronoTz::UTC, 2026, 1, 1, 9), ChronoTz::UTC);
+
+        // started before the window, but still going on in it
+        assert_eq!(
+            daily.occurrences(utc(2026, 1, 2, 9, 30), utc(2026, 1, 3, 9, 0)).unwrap(),
+            vec![utc(2026, 1, 2, 9, 0)],
+        );
+        assert!(daily.occurrences(utc(2026, 1, 2, 10, 0), utc(2026, 1, 3, 9, 0)).unwrap().is_empty());
+        assert!(daily.has_occurrence(utc(2026, 1, 5, 9, 0)).unwrap());
+        assert!(!daily.has_occurrence(utc(2026, 1, 5, 10, 0)).unwrap());
+    }
+
+    #[test]
+    fn occurrences_keep_their_wall_clock_time_across_dst()
+    {
+        // Berlin moves from UTC+1 to UTC+2 on 2026-03-29
+        let mut daily = recurrence(RecurrenceType::Daily, local(Berlin, 2026, 3, 27, 9), Berlin);
+        daily.repetitions = Some(4);
+
+        assert_eq!(all_occurrences(&daily), vec![
+            utc(2026, 3, 27, 8, 0),
+            utc(2026, 3, 28, 8, 0),
+            utc(2026, 3, 29, 7, 0),
+            utc(2026, 3, 30, 7, 0),
+        ]);
+
+        // the until is an instant, a week after the start in UTC+1
+        let mut weekly = recurrence(RecurrenceType::Weekly, local(Berlin, 2026, 3, 23, 9), Berlin);
+        weekly.repetitions  = Some(1);
+        weekly.days_of_week = vec![Weekday::Monday];
+
+        assert_eq!(all_occurrences(&weekly), vec![utc(2026, 3, 23, 8, 0), utc(2026, 3, 30, 7, 0)]);
+        assert_eq!(weekly.ical_rrule().unwrap(), "FREQ=WEEKLY;INTERVAL=1;BYDAY=MO;UNTIL=20260330T080000Z");
+    }
+
+    #[test]
+    fn rules_keep_their_parts_and_exdates()
+    {
+        let mut rule = recurrence(RecurrenceType::Rule, local(Berlin, 2026, 10, 5, 9), Berlin);
+        rule.rrule   = Some("RRULE:FREQ=MONTHLY;BYDAY=1MO;COUNT=3".to_owned());
+        rule.exdates = vec![local(Berlin, 2026, 11, 2, 9)];
+
+        // 9:00 in Berlin, in summer time on October 5th only
+        assert_eq!(all_occurrences(&rule), vec![utc(2026, 10, 5, 7, 0), utc(2026, 12, 7, 8, 0)]);
+        assert_eq!(rule.ical_rrule().unwrap(), "FREQ=MONTHLY;BYDAY=1MO;COUNT=3");
+
+        rule.end_date = Some(local(Berlin, 2026, 12, 1, 0));
+
+        // the until is the last occurrence left, the excluded one is skipped
+        assert_eq!(all_occurrences(&rule), vec![utc(2026, 10, 5, 7, 0)]);
+        assert_eq!(rule.ical_rrule().unwrap(), "FREQ=MONTHLY;BYDAY=1MO;UNTIL=20261005T070000Z");
+    }
+
+    #[test]
+    fn materialized_schedules_become_exdates_and_edits()
+    {
+        let generated: Vec<DateTime<Utc>> = (1..=5).map(|day| utc(2026, 1, day, 9, 0)).collect();
+        let hour = Duration::hours(1);
+
+        let schedules = vec![
+            (utc(2026, 1, 1, 9, 0), utc(2026, 1, 1, 10, 0)),
+            // the 2nd was deleted, the 3rd moved and the 4th made longer
+            (utc(2026, 1, 3, 14, 0), utc(2026, 1, 3, 15, 0)),
+            (utc(2026, 1, 4, 9, 0), utc(2026, 1, 4, 11, 0)),
+            (utc(2026, 1, 5, 9, 0), utc(2026, 1, 5, 10, 0)),
+        ];
+
+        assert_eq!(adopt(&generated, hour, &schedules), Adoption {
+            exdates : vec![utc(2026, 1, 2, 9, 0)],
+            edited  : vec![
+                (utc(2026, 1, 4, 9, 0), (utc(2026, 1, 4, 9, 0), utc(2026, 1, 4, 11, 0))),
+                (utc(2026, 1, 3, 9, 0), (utc(2026, 1, 3, 14, 0), utc(2026, 1, 3, 15, 0))),
+            ],
+            detached: Vec::new(),
+        });
+
+        // more schedules than generated times, like a rule that changed meaning
+        let extra = (utc(2026, 1, 1, 18, 0), utc(2026, 1, 1, 19, 0));
+
+        assert_eq!(adopt(&generated[..1], hour, &[schedules[0], extra]), Adoption {
+            detached: vec![extra],
+            ..Adoption::default()
+        });
+    }
+
+    #[test]
+    fn sub_daily_rules_are_told_apart()
+    {
+        assert!(is_sub_daily("FREQ=HOURLY"));
+        assert!(is_sub_daily("RRULE:FREQ=MINUTELY;COUNT=5"));
+        assert!(is_sub_daily("FREQ=DAILY;BYHOUR=9,17"));
+        assert!(!is_sub_daily("FREQ=DAILY;BYHOUR=9;BYMINUTE=30"));
+        assert!(!is_sub_daily("FREQ=WEEKLY;BYDAY=MO,WE"));
+        assert!(!is_sub_daily("not a rule"));
+    }
+}
@@ -0,0 +1,79 @@
+/// Escapes text for HTML content and quoted attributes.
+pub fn escape_html(text: &str) -> String
+{
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Replaces the `{{name}}` placeholders of a template in a single pass, so
+/// placeholders written in the values themselves are left as they are.
+/// Unknown placeholders are kept too.
+pub fn render(template: &str, values: &[(&str, String)]) -> String
+{
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest     = template;
+
+    while let Some(start) = rest.find("{{")
+    {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let value = rest.find("}}").and_then(|end|
+        {
+            let name = &rest[2..end];
+            values.iter()
+                .find(|(placeholder, _)| *placeholder == name)
+                .map(|(_, value)| (value, end + 2))
+        });
+
+        match value
+        {
+            Some((value, length)) =>
+            {
+                rendered.push_str(value);
+                rest = &rest[length..];
+            },
+            None =>
+            {
+                rendered.push_str("{{");
+                rest = &rest[2..];
+            },
+        }
+    }
+
+    rendered.push_str(rest);
+
+    rendered
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn renders_placeholders()
+    {
+        let rendered = render("{{greeting}}, {{name}}! {{unknown}} {{name", &[
+            ("greeting", "Hi".to_owned()),
+            ("name", "Ana".to_owned()),
+        ]);
+
+        assert_eq!(rendered, "Hi, Ana! {{unknown}} {{name");
+    }
+
+    #[test]
+    fn leaves_placeholders_in_values_alone()
+    {
+        let rendered = render("{{event_name}}: {{description}} {{accept_url}}", &[
+            ("event_name", "{{accept_url}}".to_owned()),
+            ("description", "{{{{accept_url}}}}".to_owned()),
+            ("accept_url", "https://example.com/accept".to_owned()),
+        ]);
+
+        assert_eq!(rendered, "{{accept_url}}: {{{{accept_url}}}} https://example.com/accept");
+    }
+}
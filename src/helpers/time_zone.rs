@@ -0,0 +1,48 @@
+use std::cmp::Ordering;
+use serde::Deserialize;
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// A time sent by a client, either as an instant with its offset or as a
+/// wall-clock time to be read in some time zone, usually the event's one.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub enum DateTimeInput
+{
+    Instant(DateTime<FixedOffset>),
+    WallClock(NaiveDateTime),
+}
+
+impl DateTimeInput
+{
+    /// Fails for wall-clock times skipped by a DST transition, and picks the
+    /// earliest instant of the ones repeated by it.
+    pub fn resolve(self, time_zone: &Tz) -> Option<DateTime<Utc>>
+    {
+        match self
+        {
+            DateTimeInput::Instant(date_time)   => Some(date_time.with_timezone(&Utc)),
+            DateTimeInput::WallClock(date_time) => time_zone.from_local_datetime(&date_time)
+                .earliest()
+                .map(|date_time| date_time.with_timezone(&Utc)),
+        }
+    }
+
+    /// Orders two times when it doesn't depend on the time zone they are read
+    /// in, that is when both are instants or both are wall-clock times.
+    pub fn compare(&self, other: &Self) -> Option<Ordering>
+    {
+        match (self, other)
+        {
+            (DateTimeInput::Instant(a), DateTimeInput::Instant(b))     => Some(a.cmp(b)),
+            (DateTimeInput::WallClock(a), DateTimeInput::WallClock(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+/// Renders an instant with the offset it has in the given time zone.
+pub fn in_time_zone(date_time: DateTime<FixedOffset>, time_zone: &Tz) -> DateTime<FixedOffset>
+{
+    date_time.with_timezone(time_zone).fixed_offset()
+}
@@ -0,0 +1,95 @@
+use std::{borrow::Cow, cmp::Ordering, collections::{BTreeMap, HashSet}, sync::LazyLock};
+use regex::Regex;
+use serde_json::{json, Value};
+use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
+use crate::helpers::time_zone::DateTimeInput;
+
+/// Colors the way the `tags.color` check constraint takes them.
+pub static COLOR_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new("^[0-9A-F]{6}$").unwrap());
+
+/// Phone numbers with an optional leading `+`, their digits possibly grouped
+/// by spaces, dashes or parentheses.
+static PHONE_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\+?[0-9 ()-]+$").unwrap());
+
+/// Counting the country code, as in E.164.
+const PHONE_DIGITS: std::ops::RangeInclusive<usize> = 7..=15;
+
+pub fn error(code: &'static str, message: &'static str) -> ValidationError
+{
+    ValidationError::new(code).with_message(Cow::Borrowed(message))
+}
+
+/// Errors of a single field, for the checks done outside the request structs.
+pub fn field_error(field: &'static str, error: ValidationError) -> ValidationErrors
+{
+    let mut errors = ValidationErrors::new();
+    errors.add(field, error);
+    errors
+}
+
+pub fn is_phone(phone: &str) -> bool
+{
+    PHONE_REGEX.is_match(phone) && PHONE_DIGITS.contains(&phone.chars().filter(char::is_ascii_digit).count())
+}
+
+/// Rejects ids given more than once, like the same tag twice.
+pub fn unique_ids(ids: &[i64]) -> Result<(), ValidationError>
+{
+    let mut seen = HashSet::new();
+
+    match ids.iter().all(|id| seen.insert(id))
+    {
+        true  => Ok(()),
+        false => Err(error("unique", "must not repeat ids")),
+    }
+}
+
+/// Requires `end_time` to be after `start_time`. Times that can only be
+/// compared once read in a time zone are checked when they are resolved.
+pub fn time_range(errors: &mut ValidationErrors, start_time: &DateTimeInput, end_time: &DateTimeInput)
+{
+    if matches!(end_time.compare(start_time), Some(Ordering::Less | Ordering::Equal))
+    {
+        errors.add("end_time", error("time_range", "must be after start_time"));
+    }
+}
+
+/// Lists the errors by field path, like `configuration.days_of_week` or
+/// `schedules[0].end_time`.
+pub fn field_errors(errors: &ValidationErrors) -> BTreeMap<String, Vec<Value>>
+{
+    let mut fields = BTreeMap::new();
+    collect_field_errors(errors, None, &mut fields);
+    fields
+}
+
+fn collect_field_errors(errors: &ValidationErrors, prefix: Option<&str>, fields: &mut BTreeMap<String, Vec<Value>>)
+{
+    for (field, kind) in errors.errors()
+    {
+        let path = match prefix
+        {
+            Some(prefix) => format!("{prefix}.{field}"),
+            None => field.to_string(),
+        };
+
+        match kind
+        {
+            ValidationErrorsKind::Field(field_errors) =>
+            {
+                fields.entry(path).or_default().extend(field_errors.iter().map(|error| json!({
+                    "code"   : error.code,
+                    "message": error.message.as_deref().unwrap_or("is invalid"),
+                })));
+            },
+            ValidationErrorsKind::Struct(errors) => collect_field_errors(errors, Some(&path), fields),
+            ValidationErrorsKind::List(items) =>
+            {
+                for (index, errors) in items
+                {
+                    collect_field_errors(errors, Some(&format!("{path}[{index}]")), fields);
+                }
+            },
+        }
+    }
+}
@@ -1,13 +1,70 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use axum::{
+    extract::{DefaultBodyLimit, FromRef},
+    middleware,
     routing::{get, put, post, delete},
     Router
 };
-use sqlx::postgres::PgPoolOptions;
+use sqlx::{postgres::PgPoolOptions, PgPool};
 use tokio::net::TcpListener;
+use helpers::{auth::{AuthKeys, Claims, InvitationTokens}, error::ApiError};
+use notifications::Channels;
+use storage::SharedStorage;
 
 mod handlers;
 mod helpers;
+mod notifications;
+mod storage;
+
+#[derive(Clone)]
+struct AppState
+{
+    db_pool          : PgPool,
+    auth_keys        : AuthKeys,
+    invitation_tokens: InvitationTokens,
+    channels         : Channels,
+    storage          : SharedStorage,
+}
+
+impl FromRef<AppState> for PgPool
+{
+    fn from_ref(state: &AppState) -> Self
+    {
+        state.db_pool.clone()
+    }
+}
+
+impl FromRef<AppState> for AuthKeys
+{
+    fn from_ref(state: &AppState) -> Self
+    {
+        state.auth_keys.clone()
+    }
+}
+
+impl FromRef<AppState> for InvitationTokens
+{
+    fn from_ref(state: &AppState) -> Self
+    {
+        state.invitation_tokens.clone()
+    }
+}
+
+impl FromRef<AppState> for Channels
+{
+    fn from_ref(state: &AppState) -> Self
+    {
+        state.channels.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedStorage
+{
+    fn from_ref(state: &AppState) -> Self
+    {
+        state.storage.clone()
+    }
+}
 
 #[tokio::main]
 async fn main() {
@@ -23,14 +80,40 @@ async fn main() {
         .await
         .expect("can't connect to database");
 
+    helpers::recurrence::adopt_legacy_schedules(&db_pool)
+        .await
+        .expect("can't adopt the schedules materialized before recurrences were expanded on read");
+
+    let reminders_interval = std::env::var("REMINDERS_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|seconds| seconds.parse().ok())
+        .unwrap_or(30);
+
+    let mut channels = Channels::default();
+
+    if let Some(email_channel) = notifications::email::EmailChannel::from_env().expect("invalid SMTP configuration")
+    {
+        channels = channels.with(handlers::user::ReminderType::Email, email_channel);
+    }
+
+    notifications::reminders::spawn(db_pool.clone(), channels.clone(), Duration::from_secs(reminders_interval));
+
+    let state = AppState {
+        db_pool          : db_pool.clone(),
+        auth_keys        : AuthKeys::from_env(),
+        invitation_tokens: InvitationTokens::from_env(),
+        channels,
+        storage          : Arc::new(storage::local::LocalStorage::from_env().expect("invalid attachments storage")),
+    };
+
     let app = Router::new()
-        .route("/health-check", get(health_check))
         .route("/users",
             get(handlers::user::list_users)
             .post(handlers::user::create_user)
         )
-        .route("/users/:id",
-            put(handlers::user::update_user)
+        .route("/users/me",
+            get(handlers::user::get_current_user)
+            .put(handlers::user::update_user)
             .delete(handlers::user::delete_user)
         )
         .route("/tags",
@@ -42,18 +125,68 @@ async fn main() {
             .delete(handlers::tag::delete_tag)
         )
         .route("/events",
-            post(handlers::event::create_event)
+            get(handlers::event::list_events)
+            .post(handlers::event::create_event)
+        )
+        .route("/events/:id",
+            get(handlers::calendar::get_event)
+            .put(handlers::event::update_event)
+            .patch(handlers::event::update_event)
+            .delete(handlers::event::delete_event)
         )
         .route("/events/:event_id/user/:user_id",
             post(handlers::event::add_user_to_event)
             .delete(handlers::event::delete_user_from_event)
         )
+        .route("/events/:id/attachments",
+            get(handlers::attachment::list_event_attachments)
+            .post(handlers::attachment::attach_to_event)
+            .layer(DefaultBodyLimit::max(handlers::attachment::MAX_UPLOAD_BYTES))
+        )
+        .route("/attachments/:id",
+            get(handlers::attachment::download_attachment)
+            .delete(handlers::attachment::delete_attachment)
+        )
+        .route("/events/:id/waitlist",
+            get(handlers::waitlist::list_waitlist)
+        )
+        .route("/events/:id/invitations",
+            get(handlers::invitation::list_event_invitations)
+            .post(handlers::invitation::invite_to_event)
+        )
+        .route("/invitations/:id",
+            delete(handlers::invitation::revoke_invitation)
+        )
+        .route("/users/:id/mentions",
+            get(handlers::mention::list_user_mentions)
+        )
+        .route("/users/me/invitations",
+            get(handlers::invitation::list_user_invitations)
+        )
+        .route("/events/:event_id/user/:user_id/rsvp",
+            put(handlers::participation::respond_to_event)
+        )
+        .route("/events/:id/comments",
+            get(handlers::comment::list_event_comments)
+            .post(handlers::comment::add_comment_to_event)
+        )
         .route("/events/:id/commnents",
-            post(handlers::event::add_comment_to_event)
+            post(handlers::comment::add_comment_to_event)
         )
         .route("/comments/:id",
-            put(handlers::event::update_comment)
-            .delete(handlers::event::delete_comment)
+            put(handlers::comment::update_comment)
+            .delete(handlers::comment::delete_comment)
+        )
+        .route("/comments/:id/history",
+            get(handlers::comment::get_comment_history)
+        )
+        .route("/comments/:id/attachments",
+            post(handlers::attachment::attach_to_comment)
+            .layer(DefaultBodyLimit::max(handlers::attachment::MAX_UPLOAD_BYTES))
+        )
+        .route("/comments/:id/reactions/:emoji",
+            post(handlers::reaction::add_reaction)
+            .delete(handlers::reaction::remove_reaction)
         ) 
         .route("/contacts", 
             post(handlers::contact::create_contact)
@@ -61,14 +194,23 @@ async fn main() {
         .route("/contacts/:id", 
             delete(handlers::contact::delete_contact)
         )
+        .route("/contacts/:id/preferred",
+            put(handlers::contact::prefer_contact)
+        )
         .route("/contacts/:contact_id/event/:event_id",
             post(handlers::user::add_reminder)
             .delete(handlers::user::remove_reminder)
         )
-        .route("/users/:user_id/reminders",
+        .route("/users/me/reminders",
             get(handlers::user::list_reminders_from_user)
         )
-        .route("/users/:user_id/contacts", 
+        .route("/users/:user_id/calendar.ics",
+            get(handlers::calendar::export_user_calendar)
+        )
+        .route("/users/me/import",
+            post(handlers::calendar::import_calendar)
+        )
+        .route("/users/me/contacts",
             get(handlers::contact::list_contacts)
         )
         .route("/schedules/:id",
@@ -80,11 +222,36 @@ async fn main() {
         .route("/schedules/user/:id",
             get(handlers::event::list_user_schedules)
         )
+        .route("/schedules/:id/rsvp",
+            put(handlers::participation::respond_to_schedule)
+            .delete(handlers::participation::clear_schedule_response)
+        )
         .route("/recurrences/:id",
-            delete(handlers::event::delete_recurrence)
+            put(handlers::event::update_recurrence)
+            .delete(handlers::event::delete_recurrence)
+        )
+        .route("/recurrences/:id/occurrences/:occurrence",
+            delete(handlers::event::delete_occurrence)
+        )
+        .route("/recurrences/:id/occurrences/:occurrence/rsvp",
+            put(handlers::participation::respond_to_occurrence)
+            .delete(handlers::participation::clear_occurrence_response)
+        )
+        .route("/freebusy",
+            post(handlers::availability::free_busy)
         )
+        .route("/freebusy/slots",
+            post(handlers::availability::find_slots)
+        )
+        .route_layer(middleware::from_extractor_with_state::<Claims, _>(state.clone()))
+        .route("/health-check", get(health_check))
+        .route("/invitations/:token/:answer",
+            get(handlers::invitation::confirm_invitation)
+            .post(handlers::invitation::answer_invitation)
+        )
+        .fallback(route_not_found)
         .layer(tower_http::cors::CorsLayer::permissive())
-        .with_state(db_pool);
+        .with_state(state);
 
     let listener = TcpListener::bind(format!("0.0.0.0:{}", app_port)).await.unwrap();
 
@@ -95,4 +262,8 @@ async fn main() {
 
 async fn health_check() -> &'static str {
     "I'm alive!"
+}
+
+async fn route_not_found() -> ApiError {
+    ApiError::NotFound("Route not found.".into())
 }
\ No newline at end of file
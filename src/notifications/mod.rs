@@ -0,0 +1,189 @@
+use std::{collections::HashMap, sync::Arc};
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, Utc};
+use sqlx::PgPool;
+use crate::handlers::{contact::ContactType, user::ReminderType};
+
+pub mod email;
+pub mod reminders;
+
+/// The event a notification is about.
+#[derive(Debug, Clone)]
+pub struct NotifiedEvent
+{
+    pub name       : String,
+    pub description: String,
+    pub tags       : Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message
+{
+    Reminder {
+        event         : NotifiedEvent,
+        occurrence: DateTime<FixedOffset>,
+    },
+    Invitation {
+        event      : NotifiedEvent,
+        inviter    : String,
+        accept_url : String,
+        decline_url: String,
+        expires_at : DateTime<Utc>,
+    },
+    Promotion {
+        event: NotifiedEvent,
+    },
+    Mention {
+        event  : NotifiedEvent,
+        author : String,
+        title  : String,
+        content: String,
+    },
+}
+
+/// A message addressed to one of the contacts of a user.
+#[derive(Debug, Clone)]
+pub struct Notification
+{
+    pub contact: String,
+    pub message: Message,
+}
+
+impl Notification
+{
+    pub fn subject(&self) -> String
+    {
+        match &self.message
+        {
+            Message::Reminder { event, .. } => format!("Reminder: {}", event.name),
+            Message::Invitation { event, .. } => format!("Invitation: {}", event.name),
+            Message::Promotion { event } => format!("You have a place: {}", event.name),
+            Message::Mention { event, author, .. } => format!("{author} mentioned you in {}", event.name),
+        }
+    }
+}
+
+/// A way of delivering notifications, like email or SMS. Errors are kept as
+/// messages in the delivery records.
+#[async_trait]
+pub trait Channel: Send + Sync
+{
+    async fn send(&self, notification: &Notification) -> Result<(), String>;
+}
+
+/// The channel of each reminder type. Notifications of a type without one
+/// fail, so reminders are recorded as failed instead of sent.
+#[derive(Clone, Default)]
+pub struct Channels(HashMap<ReminderType, Arc<dyn Channel>>);
+
+impl Channels
+{
+    pub fn with(mut self, reminder_type: ReminderType, channel: impl Channel + 'static) -> Self
+    {
+        self.0.insert(reminder_type, Arc::new(channel));
+        self
+    }
+
+    pub async fn send(&self, reminder_type: ReminderType, notification: &Notification) -> Result<(), String>
+    {
+        match self.0.get(&reminder_type)
+        {
+            Some(channel) => channel.send(notification).await,
+            None => Err(format!("No channel for {reminder_type:?} notifications.")),
+        }
+    }
+}
+
+/// The channel notifications to a contact go through.
+pub fn contact_channel(contact_type: ContactType) -> ReminderType
+{
+    match contact_type
+    {
+        ContactType::Email => ReminderType::Email,
+        ContactType::Phone => ReminderType::SMS,
+    }
+}
+
+/// Sends a message in the background, as answering requests shouldn't wait
+/// for it. Failures are logged.
+pub fn notify(channels: &Channels, recipients: Vec<(ReminderType, String)>, message: Message)
+{
+    let channels = channels.clone();
+
+    tokio::spawn(async move
+    {
+        for (reminder_type, contact) in recipients
+        {
+            let notification = Notification { contact, message: message.clone() };
+
+            if let Err(e) = channels.send(reminder_type, &notification).await
+            {
+                eprintln!("Notification to {} failed: {e}", notification.contact);
+            }
+        }
+    });
+}
+
+/// Sends a message to every contact of a user.
+pub async fn notify_user(db_pool: &PgPool, channels: &Channels, user_id: i64, message: Message) -> Result<(), sqlx::Error>
+{
+    let recipients = sqlx::query!(
+        r#"SELECT type AS "type: ContactType", contact FROM users_contacts WHERE user_id = $1 ORDER BY id"#,
+        user_id
+    )
+    .fetch_all(db_pool)
+    .await?
+    .into_iter()
+    .map(|contact| (contact_channel(contact.r#type), contact.contact))
+    .collect();
+
+    notify(channels, recipients, message);
+
+    Ok(())
+}
+
+/// Sends a message to the preferred contact of a user, or to their first one
+/// when none is preferred.
+pub async fn notify_preferred(db_pool: &PgPool, channels: &Channels, user_id: i64, message: Message) -> Result<(), sqlx::Error>
+{
+    let recipients = sqlx::query!(
+        r#"SELECT type AS "type: ContactType", contact FROM users_contacts
+        WHERE user_id = $1
+        ORDER BY preferred DESC, id
+        LIMIT 1"#,
+        user_id
+    )
+    .fetch_all(db_pool)
+    .await?
+    .into_iter()
+    .map(|contact| (contact_channel(contact.r#type), contact.contact))
+    .collect();
+
+    notify(channels, recipients, message);
+
+    Ok(())
+}
+
+/// The name, description and tags of an event.
+pub async fn notified_event(db_pool: &PgPool, event_id: i64) -> Result<NotifiedEvent, sqlx::Error>
+{
+    let event = sqlx::query!(
+        r#"SELECT events.name, events.description,
+            ARRAY(
+                SELECT tags.name FROM tags
+                JOIN events_tags ON events_tags.tag_id = tags.id
+                WHERE events_tags.event_id = events.id
+                ORDER BY tags.name
+            ) AS "tags!"
+        FROM events WHERE id = $1"#,
+        event_id
+    )
+    .fetch_one(db_pool)
+    .await?;
+
+    Ok(NotifiedEvent {
+        name       : event.name,
+        description: event.description,
+        tags       : event.tags,
+    })
+}
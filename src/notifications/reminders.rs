@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use sqlx::{FromRow, PgPool};
+use tokio::task::JoinHandle;
+use crate::{
+    handlers::user::ReminderType,
+    helpers::recurrence::{self, Schedule},
+    notifications::{Channels, Message, Notification, NotifiedEvent},
+};
+
+/// How late a reminder can still be sent, like after the server was down.
+const MAX_LATENESS_MINUTES: i64 = 10;
+
+/// Failed deliveries are retried on the following scans up to this many attempts.
+const MAX_ATTEMPTS: i32 = 5;
+
+/// A delivery pending for longer was claimed by a worker that stopped before
+/// sending it, so it can be claimed again.
+const CLAIM_TIMEOUT_MINUTES: i32 = 5;
+
+#[derive(FromRow)]
+struct ScheduledReminder
+{
+    id            : i64,
+    event_id      : i64,
+    r#type        : ReminderType,
+    minutes_before: i32,
+    contact       : String,
+    name          : String,
+    description   : String,
+    time_zone     : String,
+}
+
+/// Scans for due reminders every `interval`, until the server stops.
+pub fn spawn(db_pool: PgPool, channels: Channels, interval: std::time::Duration) -> JoinHandle<()>
+{
+    tokio::spawn(async move
+    {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop
+        {
+            ticker.tick().await;
+
+            if let Err(e) = dispatch_due_reminders(&db_pool, &channels, Utc::now()).await
+            {
+                eprintln!("Reminders dispatch failed: {e}");
+            }
+        }
+    })
+}
+
+/// Sends the reminders whose time came for an occurrence of their event,
+/// returning how many were sent. Every delivery is claimed in
+/// `reminder_deliveries` before sending, so a reminder fires once per
+/// occurrence, even with several workers or across restarts.
+pub async fn dispatch_due_reminders(
+    db_pool: &PgPool,
+    channels: &Channels,
+    now: DateTime<Utc>,
+) -> Result<usize, sqlx::Error>
+{
+    let reminders = sqlx::query_as::<_, ScheduledReminder>
+        ("SELECT reminders.id, reminders.event_id, reminders.type, reminders.minutes_before,
+            users_contacts.contact, events.name, events.description, events.time_zone
+        FROM reminders
+        JOIN users_contacts ON users_contacts.id = reminders.user_contact_id
+        JOIN events ON events.id = reminders.event_id")
+        .fetch_all(db_pool)
+        .await?;
+
+    let Some(max_minutes_before) = reminders.iter().map(|reminder| reminder.minutes_before).max() else
+    {
+        return Ok(0);
+    };
+
+    let mut event_ids: Vec<i64> = reminders.iter().map(|reminder| reminder.event_id).collect();
+    event_ids.sort_unstable();
+    event_ids.dedup();
+
+    let from = now - Duration::minutes(MAX_LATENESS_MINUTES);
+    let to   = now + Duration::minutes(max_minutes_before as i64) + Duration::seconds(1);
+
+    let mut schedules: HashMap<i64, Vec<Schedule>> = HashMap::new();
+    for schedule in recurrence::list_schedules(&mut *db_pool.acquire().await?, &event_ids, from, to).await?
+    {
+        schedules.entry(schedule.event_id).or_default().push(schedule);
+    }
+
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    for (event_id, tag) in sqlx::query_as::<_, (i64, String)>
+        ("SELECT events_tags.event_id::BIGINT, tags.name FROM tags
+        JOIN events_tags ON events_tags.tag_id = tags.id
+        WHERE events_tags.event_id = ANY($1)
+        ORDER BY tags.name")
+        .bind(&event_ids)
+        .fetch_all(db_pool)
+        .await?
+    {
+        tags.entry(event_id).or_default().push(tag);
+    }
+
+    let mut sent = 0;
+
+    for reminder in &reminders
+    {
+        for schedule in schedules.get(&reminder.event_id).into_iter().flatten()
+        {
+            let start_time = schedule.start_time.with_timezone(&Utc);
+            let due_time   = start_time - Duration::minutes(reminder.minutes_before as i64);
+
+            if due_time > now || due_time <= from
+            {
+                continue;
+            }
+
+            let event = NotifiedEvent {
+                name       : reminder.name.clone(),
+                description: reminder.description.clone(),
+                tags       : tags.get(&reminder.event_id).cloned().unwrap_or_default(),
+            };
+
+            if deliver(db_pool, channels, reminder, event, start_time).await?
+            {
+                sent += 1;
+            }
+        }
+    }
+
+    Ok(sent)
+}
+
+/// Claims and sends a reminder for an occurrence, recording the outcome. Gives
+/// `false` when it was already delivered, or is being delivered elsewhere.
+async fn deliver(
+    db_pool: &PgPool,
+    channels: &Channels,
+    reminder: &ScheduledReminder,
+    event: NotifiedEvent,
+    start_time: DateTime<Utc>,
+) -> Result<bool, sqlx::Error>
+{
+    let claimed = sqlx::query_scalar!(
+        "INSERT INTO reminder_deliveries (reminder_id, occurrence_start_time)
+        VALUES ($1, $2)
+        ON CONFLICT (reminder_id, occurrence_start_time) DO UPDATE
+        SET status = 'pending', attempts = reminder_deliveries.attempts + 1, claimed_at = NOW()
+        WHERE (reminder_deliveries.status = 'failed' AND reminder_deliveries.attempts < $3)
+            OR (reminder_deliveries.status = 'pending'
+                AND reminder_deliveries.claimed_at < NOW() - MAKE_INTERVAL(mins => $4))
+        RETURNING id",
+        reminder.id,
+        start_time,
+        MAX_ATTEMPTS,
+        CLAIM_TIMEOUT_MINUTES
+    )
+    .fetch_optional(db_pool)
+    .await?;
+
+    let Some(delivery_id) = claimed else
+    {
+        return Ok(false);
+    };
+
+    let time_zone: Tz = reminder.time_zone.parse().unwrap_or(Tz::UTC);
+
+    let notification = Notification {
+        contact: reminder.contact.clone(),
+        message: Message::Reminder {
+            event,
+            occurrence: start_time.with_timezone(&time_zone).fixed_offset(),
+        },
+    };
+
+    match channels.send(reminder.r#type, &notification).await
+    {
+        Ok(()) =>
+        {
+            sqlx::query!(
+                "UPDATE reminder_deliveries SET status = 'sent', last_error = NULL, delivered_at = NOW()
+                WHERE id = $1",
+                delivery_id
+            )
+            .execute(db_pool)
+            .await?;
+
+            Ok(true)
+        },
+        Err(e) =>
+        {
+            sqlx::query!(
+                "UPDATE reminder_deliveries SET status = 'failed', last_error = $2 WHERE id = $1",
+                delivery_id,
+                e
+            )
+            .execute(db_pool)
+            .await?;
+
+            Ok(false)
+        },
+    }
+}
@@ -0,0 +1,63 @@
+use std::{io::ErrorKind, path::PathBuf};
+use async_trait::async_trait;
+use crate::storage::Storage;
+
+/// Keeps every file in a directory of the server, named by its key.
+pub struct LocalStorage
+{
+    directory: PathBuf,
+}
+
+impl LocalStorage
+{
+    /// Reads `ATTACHMENTS_DIR`, `attachments` by default, creating it when
+    /// missing.
+    pub fn from_env() -> Result<Self, String>
+    {
+        let directory = PathBuf::from(std::env::var("ATTACHMENTS_DIR").unwrap_or("attachments".to_owned()));
+
+        std::fs::create_dir_all(&directory)
+            .map_err(|e| format!("Can't create {}: {e}", directory.display()))?;
+
+        Ok(LocalStorage { directory })
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage
+{
+    /// Writes a temporary file first, so readers never see half of one.
+    async fn put(&self, key: &str, bytes: &[u8]) -> Result<(), String>
+    {
+        let path      = self.directory.join(key);
+        let temporary = self.directory.join(format!("{key}.part"));
+
+        tokio::fs::write(&temporary, bytes)
+            .await
+            .map_err(|e| format!("Can't write {}: {e}", temporary.display()))?;
+
+        tokio::fs::rename(&temporary, &path)
+            .await
+            .map_err(|e| format!("Can't move {} to {}: {e}", temporary.display(), path.display()))
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, String>
+    {
+        let path = self.directory.join(key);
+
+        tokio::fs::read(&path)
+            .await
+            .map_err(|e| format!("Can't read {}: {e}", path.display()))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), String>
+    {
+        let path = self.directory.join(key);
+
+        match tokio::fs::remove_file(&path).await
+        {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(format!("Can't delete {}: {e}", path.display())),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+
+pub mod local;
+
+/// Where the contents of attachments are kept, by keys the API chooses. Errors
+/// are messages, logged but not sent to clients.
+#[async_trait]
+pub trait Storage: Send + Sync
+{
+    async fn put(&self, key: &str, bytes: &[u8]) -> Result<(), String>;
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, String>;
+
+    /// Deleting what isn't stored succeeds.
+    async fn delete(&self, key: &str) -> Result<(), String>;
+}
+
+/// The storage of the application, shared by every request.
+pub type SharedStorage = Arc<dyn Storage>;